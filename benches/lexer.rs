@@ -0,0 +1,173 @@
+//! Compares the single-pass scanner in `src/lexer.rs` with the `RegexSet`
+//! lexer it replaced, over a generated multi-megabyte program.
+//!
+//! Run with `cargo bench --bench lexer`.
+#![allow(dead_code)]
+
+use std::time::{Duration, Instant};
+
+use regex::RegexSet;
+
+#[path = "../src/diagnostic.rs"]
+mod diagnostic;
+#[path = "../src/lexer.rs"]
+mod lexer;
+
+use lexer::{IntTy, Lexer, Token};
+
+const INPUT_SIZE: usize = 4 << 20;
+
+/// Patterns of the old lexer, in priority order.
+const PATTERNS: [&str; 21] = [
+    r"^int$",
+    r"^str$",
+    r"^bool$",
+    r"^while$",
+    r#"^"([^"\\]|\\.)*"$"#,
+    r"^[0-9]+$",
+    r"^\($",
+    r"^\)$",
+    r"^=$",
+    r"^==$",
+    r"^>$",
+    r"^<$",
+    r"^print$",
+    r"^\{$",
+    r"^}$",
+    r"^[ \n\t\r]$",
+    r"^;$",
+    r"^if$",
+    r"^\*$",
+    r"^\+$",
+    r"^[A-Za-z]([A-Za-z]|[0-9])*$",
+];
+
+/// The lexer as it was before the scanner rewrite: grows a candidate one
+/// character at a time, re-running every pattern on it twice per character.
+struct RegexLexer {
+    match_set: RegexSet,
+}
+
+impl RegexLexer {
+    fn new() -> Self {
+        Self {
+            match_set: RegexSet::new(PATTERNS).unwrap(),
+        }
+    }
+
+    fn match_token(&self, txt: &str) -> Option<usize> {
+        self.match_set.matches(txt).into_iter().next()
+    }
+
+    fn push_token(tokens: &mut Vec<Token>, pattern: usize, data: &str) {
+        let token = match pattern {
+            0 => Token::Int(IntTy::I64),
+            1 => Token::Str,
+            2 => Token::Bool,
+            3 => Token::While,
+            4 => Token::StringLit(data[1..data.len() - 1].to_string()),
+            5 => Token::IntLit(data.parse().unwrap(), None),
+            6 => Token::OpenParen,
+            7 => Token::CloseParen,
+            8 => Token::Assign,
+            9 => Token::Equals,
+            10 => Token::GT,
+            11 => Token::LT,
+            12 => Token::Print,
+            13 => Token::OpenBrace,
+            14 => Token::CloseBrace,
+            15 => return,
+            16 => Token::Semi,
+            17 => Token::If,
+            18 => Token::Times,
+            19 => Token::Plus,
+            _ => Token::Name(data.to_string()),
+        };
+        tokens.push(token)
+    }
+
+    fn tokenize(&self, code: &str) -> Vec<Token> {
+        let mut tokens = vec![];
+        let mut prev = "".to_string();
+        for char in code.chars() {
+            let new = format!("{}{}", prev, char);
+            if self.match_token(&new).is_some() {
+                prev.push(char);
+                continue;
+            }
+            match self.match_token(&prev) {
+                None => prev.push(char),
+                Some(pattern) => {
+                    Self::push_token(&mut tokens, pattern, &prev);
+                    prev = char.to_string();
+                }
+            }
+        }
+        if let Some(pattern) = self.match_token(&prev) {
+            Self::push_token(&mut tokens, pattern, &prev);
+        }
+        tokens.push(Token::EOF);
+        tokens
+    }
+}
+
+/// Builds a program of roughly `size` bytes out of the constructs in
+/// `fib.txt`, with an occasional very long identifier and string literal.
+fn generate(size: usize) -> String {
+    let mut code = String::with_capacity(size + 4096);
+    let mut i = 0;
+    while code.len() < size {
+        code += &format!("int value{} = {} * 3 + value{}\n", i, i % 1000, i / 2);
+        code += &format!("str text{} = \"a string literal, number {}\"\n", i, i);
+        code += &format!(
+            "while (value{} < 20) {{\n    print(value{})\n    value{} = value{} + 1\n}}\n",
+            i, i, i, i
+        );
+        if i % 2000 == 0 {
+            code += &format!("int {} = 1\n", "n".repeat(2000));
+            code += &format!("str long = \"{}\"\n", "s".repeat(2000));
+        }
+        i += 1;
+    }
+    code
+}
+
+fn throughput(bytes: usize, time: Duration) -> f64 {
+    bytes as f64 / (1 << 20) as f64 / time.as_secs_f64()
+}
+
+fn main() {
+    let code = generate(INPUT_SIZE);
+    println!("input: {:.1} MiB", code.len() as f64 / (1 << 20) as f64);
+
+    let start = Instant::now();
+    let old_tokens = RegexLexer::new().tokenize(&code);
+    let old_time = start.elapsed();
+
+    let start = Instant::now();
+    let new_tokens = Lexer::new().tokenize(&code).unwrap();
+    let new_time = start.elapsed();
+
+    let new_tokens: Vec<&Token> = new_tokens.iter().map(|tok| &tok.token).collect();
+    assert_eq!(
+        format!("{:?}", old_tokens.iter().collect::<Vec<_>>()),
+        format!("{:?}", new_tokens),
+        "scanner and regex lexer disagree"
+    );
+
+    println!(
+        "regex lexer: {:>10.2?} ({:.1} MiB/s)",
+        old_time,
+        throughput(code.len(), old_time)
+    );
+    println!(
+        "scanner:     {:>10.2?} ({:.1} MiB/s)",
+        new_time,
+        throughput(code.len(), new_time)
+    );
+    println!(
+        "{} tokens, {:.1}x faster",
+        new_tokens.len(),
+        old_time.as_secs_f64() / new_time.as_secs_f64()
+    );
+}
@@ -1,3 +1,6 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
 use std::process::Command;
 
 fn main() {
@@ -7,8 +10,47 @@ fn main() {
     )) {
         println!("cargo:rustc-link-lib=dylib=ffi");
     }
-    Command::new("rustc")
-        .args(&["--crate-type", "staticlib", "src/io.rs"])
-        .status()
-        .unwrap();
+    build_runtime();
+}
+
+/// Compiles `src/io.rs`, the runtime compiled programs link against, into
+/// `OUT_DIR`, along with the list of system libraries it needs. `link.rs`
+/// embeds both in the compiler from there.
+fn build_runtime() {
+    println!("cargo:rerun-if-changed=src/io.rs");
+    println!("cargo:rerun-if-changed=build.rs");
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let target = env::var("TARGET").unwrap();
+    let output = Command::new(rustc)
+        .args(["--crate-type", "staticlib", "--crate-name", "crabby_rt"])
+        .args([
+            "--edition",
+            "2021",
+            "-C",
+            "opt-level=2",
+            "--target",
+            &target,
+        ])
+        .args(["--print", "native-static-libs"])
+        .arg("src/io.rs")
+        .arg("-o")
+        .arg(out_dir.join("libcrabby_rt.a"))
+        .output()
+        .expect("could not run rustc to build the runtime library");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "building the runtime library failed:\n{}",
+        stderr
+    );
+
+    // rustc prints the list as a note, e.g.
+    // `note: native-static-libs: -lgcc_s -lutil -lrt -lpthread -lm -ldl -lc`.
+    let libs = stderr
+        .lines()
+        .find_map(|line| line.split_once("native-static-libs: "))
+        .map(|(_, libs)| libs.trim())
+        .expect("rustc did not print the runtime's native libraries");
+    fs::write(out_dir.join("libcrabby_rt.libs"), libs).unwrap();
 }
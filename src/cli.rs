@@ -0,0 +1,276 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const USAGE: &str = "usage: crabby build <file> [-o <output>] [--emit=<kind>] [options]
+       crabby run <file> [options]
+
+commands:
+    build                   compile <file> to an executable
+    run                     compile <file> in memory and run it
+
+options:
+    -o <output>             write the result to <output> (build only)
+    --emit=<kind>           stop after producing one of (build only):
+                            tokens, ast, llvm-ir, llvm-bc, asm, obj, exe (default)
+    -l <lib>                link with the library <lib> (build only)
+    -L <dir>                search <dir> for libraries to link with (build only)
+    --linker=<path>         link with <path> instead of $CC, cc, clang or gcc (build only)
+    --target=<triple>       generate code for <triple>, e.g. aarch64-unknown-linux-gnu,
+                            instead of this machine (build only, not with --emit=exe)
+    --cpu=<name>            generate code for the CPU <name> (build only, default generic)
+    --features=<list>       enable or disable CPU features, e.g. +neon,-fp-armv8
+                            (build only)
+    -O<level>               optimize at <level>: 0 (default), 1, 2, 3 or s for size
+    --passes=<pipeline>     run this LLVM pass pipeline instead of the one -O picks,
+                            e.g. --passes=mem2reg,instcombine,gvn or default<O2>
+    --overflow-checks       stop the program when `+`, `-` or `*` overflows
+                            (default at -O0)
+    --no-overflow-checks    let overflowing arithmetic wrap around (default otherwise)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Build,
+    /// Runs the program with the JIT instead of writing anything out.
+    Run,
+}
+
+/// How much to optimize, set with `-O<level>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+    O3,
+    /// Like `O2`, but favouring smaller code.
+    Os,
+}
+
+impl OptLevel {
+    fn parse(level: &str) -> Result<Self, String> {
+        match level {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            "3" => Ok(OptLevel::O3),
+            "s" => Ok(OptLevel::Os),
+            _ => Err(format!("unknown optimization level `{}`", level)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    LlvmIr,
+    LlvmBc,
+    Asm,
+    Obj,
+    Exe,
+}
+
+impl Emit {
+    fn parse(kind: &str) -> Result<Self, String> {
+        match kind {
+            "tokens" => Ok(Emit::Tokens),
+            "ast" => Ok(Emit::Ast),
+            "llvm-ir" => Ok(Emit::LlvmIr),
+            "llvm-bc" => Ok(Emit::LlvmBc),
+            "asm" => Ok(Emit::Asm),
+            "obj" => Ok(Emit::Obj),
+            "exe" => Ok(Emit::Exe),
+            _ => Err(format!("unknown emit kind `{}`", kind)),
+        }
+    }
+
+    /// Extension used for the default output name, `None` for stdout.
+    fn extension(&self) -> Option<&'static str> {
+        match self {
+            Emit::Tokens | Emit::Ast => None,
+            Emit::LlvmIr => Some("ll"),
+            Emit::LlvmBc => Some("bc"),
+            Emit::Asm => Some("s"),
+            Emit::Obj => Some("o"),
+            Emit::Exe => Some(""),
+        }
+    }
+}
+
+pub struct Options {
+    pub command: Command,
+    pub input: PathBuf,
+    pub output: Option<PathBuf>,
+    pub emit: Emit,
+    pub opt_level: OptLevel,
+    /// A pass pipeline, in the syntax of LLVM's `opt -passes=`, to run
+    /// instead of the standard one for `opt_level`.
+    pub passes: Option<String>,
+    /// Set by `--overflow-checks` or `--no-overflow-checks`, see
+    /// `overflow_checks`.
+    overflow_checks: Option<bool>,
+    pub linker: Option<PathBuf>,
+    /// Extra libraries to link with, without the `-l`.
+    pub libs: Vec<String>,
+    pub lib_dirs: Vec<PathBuf>,
+    /// The target triple to generate code for, `None` for the host.
+    pub target: Option<String>,
+    pub cpu: String,
+    pub features: String,
+}
+
+impl Options {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let command = match args.next().as_deref() {
+            Some("build") => Command::Build,
+            Some("run") => Command::Run,
+            Some(cmd) => return Err(format!("unknown command `{}`", cmd)),
+            None => return Err("missing command".to_string()),
+        };
+
+        let mut input = None;
+        let mut output = None;
+        let mut emit = Emit::Exe;
+        let mut opt_level = OptLevel::O0;
+        let mut passes = None;
+        let mut overflow_checks = None;
+        let mut linker = None;
+        let mut libs = vec![];
+        let mut lib_dirs = vec![];
+        let mut target = None;
+        let mut cpu = "generic".to_string();
+        let mut features = String::new();
+        while let Some(arg) = args.next() {
+            if command == Command::Run && is_build_only(&arg) {
+                return Err(format!("`{}` cannot be used with `run`", arg));
+            } else if arg == "-o" {
+                match args.next() {
+                    Some(path) => output = Some(PathBuf::from(path)),
+                    None => return Err("`-o` expects a path".to_string()),
+                }
+            } else if let Some(kind) = arg.strip_prefix("--emit=") {
+                emit = Emit::parse(kind)?;
+            } else if let Some(lib) = arg.strip_prefix("-l") {
+                libs.push(value_or_next(lib, &mut args, "-l", "a library name")?);
+            } else if let Some(dir) = arg.strip_prefix("-L") {
+                let dir = value_or_next(dir, &mut args, "-L", "a directory")?;
+                lib_dirs.push(PathBuf::from(dir));
+            } else if let Some(path) = arg.strip_prefix("--linker=") {
+                linker = Some(PathBuf::from(path));
+            } else if let Some(triple) = arg.strip_prefix("--target=") {
+                target = Some(triple.to_string());
+            } else if let Some(name) = arg.strip_prefix("--cpu=") {
+                cpu = name.to_string();
+            } else if let Some(list) = arg.strip_prefix("--features=") {
+                features = list.to_string();
+            } else if let Some(level) = arg.strip_prefix("-O") {
+                opt_level = OptLevel::parse(level)?;
+            } else if let Some(pipeline) = arg.strip_prefix("--passes=") {
+                passes = Some(pipeline.to_string());
+            } else if arg == "--overflow-checks" {
+                overflow_checks = Some(true);
+            } else if arg == "--no-overflow-checks" {
+                overflow_checks = Some(false);
+            } else if arg.starts_with('-') {
+                return Err(format!("unknown option `{}`", arg));
+            } else if input.is_none() {
+                input = Some(PathBuf::from(arg));
+            } else {
+                return Err(format!("unexpected argument `{}`", arg));
+            }
+        }
+
+        // The runtime library is only built for the host, so there is nothing
+        // to link a program for another target with.
+        if target.is_some() && emit == Emit::Exe {
+            return Err("`--target` needs `--emit=obj` or another non-executable kind".to_string());
+        }
+
+        let opts = match input {
+            Some(input) => Self {
+                command,
+                input,
+                output,
+                emit,
+                opt_level,
+                passes,
+                overflow_checks,
+                linker,
+                libs,
+                lib_dirs,
+                target,
+                cpu,
+                features,
+            },
+            None => return Err("missing input file".to_string()),
+        };
+        // An input without an extension has the same default name as the
+        // executable built from it.
+        if opts.command == Command::Build {
+            if let Some(output) = opts.output_path() {
+                if is_same_file(&output, &opts.input) {
+                    return Err(format!(
+                        "writing {} would overwrite the input file, pick another name with -o",
+                        output.display()
+                    ));
+                }
+            }
+        }
+        Ok(opts)
+    }
+
+    /// Whether overflowing arithmetic stops the program instead of wrapping.
+    /// Unless asked otherwise, only unoptimized debug builds check.
+    pub fn overflow_checks(&self) -> bool {
+        self.overflow_checks
+            .unwrap_or(self.opt_level == OptLevel::O0)
+    }
+
+    /// Where the result should be written, `None` meaning stdout.
+    pub fn output_path(&self) -> Option<PathBuf> {
+        if let Some(output) = &self.output {
+            return Some(output.clone());
+        }
+        let ext = self.emit.extension()?;
+        let stem = self.input.file_stem().unwrap_or(self.input.as_os_str());
+        let path = PathBuf::from(stem);
+        if ext.is_empty() {
+            Some(path)
+        } else {
+            Some(path.with_extension(ext))
+        }
+    }
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Options that only mean something when writing an executable.
+fn is_build_only(arg: &str) -> bool {
+    arg == "-o"
+        || arg.starts_with("--emit=")
+        || arg.starts_with("--linker=")
+        || arg.starts_with("--target=")
+        || arg.starts_with("--cpu=")
+        || arg.starts_with("--features=")
+        || arg.starts_with("-l")
+        || arg.starts_with("-L")
+}
+
+/// The value of an option that can be written either attached, as in `-lm`,
+/// or as the next argument, as in `-l m`.
+fn value_or_next(
+    attached: &str,
+    args: &mut impl Iterator<Item = String>,
+    option: &str,
+    expected: &str,
+) -> Result<String, String> {
+    if !attached.is_empty() {
+        return Ok(attached.to_string());
+    }
+    args.next()
+        .ok_or_else(|| format!("`{}` expects {}", option, expected))
+}
@@ -1,213 +1,399 @@
-use std::{
-    collections::{btree_map::Values, HashMap},
-    vec,
-};
+use std::collections::HashMap;
 
 use inkwell::{
-    basic_block::BasicBlock,
-    builder::{self, Builder},
+    attributes::{Attribute, AttributeLoc},
+    builder::Builder,
     context::Context,
     module::Module,
-    passes::PassManager,
-    types::BasicMetadataTypeEnum,
-    values::{
-        BasicMetadataValueEnum, BasicValue, CallSiteValue, FunctionValue, InstructionValue,
-        PointerValue,
-    },
-    IntPredicate,
+    types::{BasicTypeEnum, IntType, PointerType},
+    values::{BasicMetadataValueEnum, BasicValueEnum, FunctionValue, IntValue, PointerValue},
+    AddressSpace, IntPredicate,
 };
 
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{IntTy, Span};
 use crate::parser::{
     decls::{Decl, Decls},
-    expr::{Expr, ExprData, Operation},
+    expr::{Expr, ExprData, Operation, PrefixOp},
     program::Program,
-    stmts::{self, AssignStmt, ControlStmt, ControlType, PrintStmt, StmtType, Stmts},
+    stmts::{AssignStmt, ControlStmt, ControlType, ElseBranch, PrintStmt, StmtType, Stmts},
     var::Var,
 };
+use crate::semantic::Types;
 
-use super::Compiler;
+use super::{CompileError, Compiler};
 
 impl<'a, 'ctx> Compiler<'a, 'ctx> {
     pub fn compile(
         context: &'ctx Context,
         builder: &'a Builder<'ctx>,
         module: &'a Module<'ctx>,
-        pass_manager: &'a PassManager<FunctionValue<'ctx>>,
         program: Program,
-    ) -> FunctionValue<'ctx> {
-        let fn_type = context.void_type().fn_type(vec![].as_slice(), false);
+        types: Types,
+        overflow_checks: bool,
+    ) -> Result<FunctionValue<'ctx>, CompileError> {
+        // `main` returns the exit code, which is always 0 for now.
+        let fn_type = context.i32_type().fn_type(vec![].as_slice(), false);
         let fn_val = module.add_function("main", fn_type, None);
         let mut compiler: Compiler<'a, 'ctx> = Compiler {
             context: context,
             builder: builder,
             module: module,
-            fpm: pass_manager,
             fn_val: fn_val,
-            variables: HashMap::new(),
+            variables: vec![],
+            functions: HashMap::new(),
+            types,
+            overflow_checks,
+        };
+        compiler.declare_runtime();
+        let main = compiler
+            .compile_program(program)
+            .map_err(CompileError::Diagnostic)?;
+        // The checker rejects every program that could make invalid IR, so
+        // a failure here is a compiler bug.
+        module
+            .verify()
+            .map_err(|err| CompileError::Internal(err.to_string()))?;
+        Ok(main)
+    }
+
+    /// Declares the functions the runtime library in `io.rs` provides.
+    fn declare_runtime(&self) {
+        let int = self.int_type(IntTy::I64);
+        let str = self.str_type();
+        let bool = self.context.bool_type();
+        let void = self.context.void_type();
+        for ty in IntTy::ALL {
+            let func = self.module.add_function(
+                &format!("print_{}", ty.name()),
+                void.fn_type(&[self.int_type(ty).into()], false),
+                None,
+            );
+            if ty.bits() < 32 {
+                let extension = if ty.is_signed() { "signext" } else { "zeroext" };
+                self.extend_param(func, extension);
+            }
+        }
+        let print_bool =
+            self.module
+                .add_function("print_bool", void.fn_type(&[bool.into()], false), None);
+        self.extend_param(print_bool, "zeroext");
+        self.module
+            .add_function("print_str", void.fn_type(&[str.into()], false), None);
+        self.module.add_function(
+            "str_eq",
+            bool.fn_type(&[str.into(), str.into()], false),
+            None,
+        );
+        self.module.add_function(
+            "str_concat",
+            str.fn_type(&[str.into(), str.into()], false),
+            None,
+        );
+        self.module
+            .add_function("str_len", int.fn_type(&[str.into()], false), None);
+        self.module
+            .add_function("runtime_panic", void.fn_type(&[str.into()], false), None);
+    }
+
+    /// The C calling convention leaves widening arguments narrower than 32
+    /// bits to the caller, so this says how for the first parameter of `func`.
+    fn extend_param(&self, func: FunctionValue<'ctx>, extension: &str) {
+        let kind = Attribute::get_named_enum_kind_id(extension);
+        func.add_attribute(
+            AttributeLoc::Param(0),
+            self.context.create_enum_attribute(kind, 0),
+        );
+    }
+
+    /// Calls a function from the runtime library declared by `declare_runtime`.
+    fn call_runtime(
+        &self,
+        name: &str,
+        args: &[BasicMetadataValueEnum<'ctx>],
+    ) -> Option<BasicValueEnum<'ctx>> {
+        let func = self.module.get_function(name).unwrap();
+        let call_name = match func.get_type().get_return_type() {
+            Some(_) => "tmp",
+            None => "",
         };
-        compiler.compile_program(program)
+        self.builder
+            .build_call(func, args, call_name)
+            .unwrap()
+            .try_as_basic_value()
+            .left()
     }
 
-    fn compile_program(&mut self, program: Program) -> FunctionValue<'ctx> {
+    fn compile_program(&mut self, program: Program) -> Result<FunctionValue<'ctx>, Diagnostic> {
+        let main = self.fn_val;
+        // Declare every function up front so calls can refer to functions
+        // defined further down, or to the function being compiled.
+        for func in &program.funcs {
+            self.declare_function(func);
+        }
+        for func in program.funcs {
+            self.compile_function(func)?;
+        }
+
+        self.fn_val = main;
+        self.variables = vec![HashMap::new()];
         let entry = self.context.append_basic_block(self.fn_val, "entry");
         self.builder.position_at_end(entry);
 
         match program.decls {
-            Some(decls) => self.compile_decls(*decls),
+            Some(decls) => self.compile_decls(*decls)?,
             None => {}
         }
 
         match program.stmts {
-            Some(stmts) => self.compile_stmts(*stmts),
+            Some(stmts) => self.compile_stmts(*stmts)?,
             None => {}
         }
 
-        self.builder.build_return(None);
-
-        if self.fn_val.verify(true) {
-            self.fpm.run_on(&self.fn_val);
-        } else {
-            println!("main is borked")
+        if self.needs_terminator() {
+            self.build_return_void();
         }
 
-        return self.fn_val;
+        return Ok(self.fn_val);
     }
 
-    fn compile_stmts(&mut self, stmts: Stmts) {
+    pub(super) fn compile_stmts(&mut self, stmts: Stmts) -> Result<(), Diagnostic> {
         match stmts.stmt {
-            StmtType::Control(control) => self.compile_control(*control),
-            StmtType::Print(print) => self.compile_print(*print),
-            StmtType::Assign(assign) => self.compile_assign(*assign),
+            StmtType::Control(control) => self.compile_control(*control)?,
+            StmtType::Print(print) => self.compile_print(*print)?,
+            StmtType::Assign(assign) => self.compile_assign(*assign)?,
+            StmtType::Return(ret) => self.compile_return(*ret)?,
+            StmtType::Call(call) => {
+                self.compile_call(&call.name, call.args)?;
+            }
         }
         match stmts.stmts {
             Some(next) => self.compile_stmts(*next),
-            None => {}
+            None => Ok(()),
         }
     }
 
-    fn compile_assign(&mut self, stmt: AssignStmt) {
-        let ptr = self.variables.get(&stmt.name).unwrap();
-        match self.compile_expr(*stmt.expr) {
-            BasicMetadataValueEnum::IntValue(int) => self.builder.build_store(*ptr, int),
-            _ => todo!(),
-        };
+    fn compile_assign(&mut self, stmt: AssignStmt) -> Result<(), Diagnostic> {
+        let (ptr, _) = self.variable(&stmt.name);
+        let value = self.compile_expr(*stmt.expr)?;
+        self.builder.build_store(ptr, value).unwrap();
+        Ok(())
     }
 
-    fn compile_control(&mut self, stmt: ControlStmt) {
+    fn compile_control(&mut self, stmt: ControlStmt) -> Result<(), Diagnostic> {
         match stmt.control_type {
             ControlType::If => {
-                let cond = self.compile_expr(*stmt.bool).into_int_value();
-                let zero_const = self.context.i16_type().const_int(0, false);
-                let cond = self
-                    .builder
-                    .build_int_compare(IntPredicate::NE, cond, zero_const, "if");
+                let cond = self.compile_bool(*stmt.bool)?;
                 let then_bb = self.context.append_basic_block(self.fn_val, "then");
+                let else_bb = stmt
+                    .else_branch
+                    .as_ref()
+                    .map(|_| self.context.append_basic_block(self.fn_val, "else"));
                 let cont_bb = self.context.append_basic_block(self.fn_val, "cont");
-                let else_bb = self.context.append_basic_block(self.fn_val, "else");
                 self.builder
-                    .build_conditional_branch(cond, then_bb, else_bb);
+                    .build_conditional_branch(cond, then_bb, else_bb.unwrap_or(cont_bb))
+                    .unwrap();
                 self.builder.position_at_end(then_bb);
-                if let Some(decls) = stmt.decls {
-                    self.compile_decls(*decls)
-                }
-                if let Some(stmts) = stmt.stmts {
-                    self.compile_stmts(*stmts)
-                }
-                self.builder.build_unconditional_branch(cont_bb);
+                self.compile_block(stmt.decls, stmt.stmts)?;
+                self.builder.build_unconditional_branch(cont_bb).unwrap();
 
-                /* TODO: implement else statements */
-                self.builder.position_at_end(else_bb);
-                self.builder.build_unconditional_branch(cont_bb);
+                if let (Some(else_bb), Some(else_branch)) = (else_bb, stmt.else_branch) {
+                    self.builder.position_at_end(else_bb);
+                    match else_branch {
+                        ElseBranch::ElseIf(control) => self.compile_control(*control)?,
+                        ElseBranch::Else { decls, stmts } => self.compile_block(decls, stmts)?,
+                    }
+                    self.builder.build_unconditional_branch(cont_bb).unwrap();
+                }
                 self.builder.position_at_end(cont_bb)
             }
             ControlType::While => {
-                let zero_const = self.context.i16_type().const_int(0, false);
+                let cond_bb = self.context.append_basic_block(self.fn_val, "loop cond");
                 let loop_bb = self.context.append_basic_block(self.fn_val, "loop body");
-                self.builder.build_unconditional_branch(loop_bb);
+                let after_bb = self.context.append_basic_block(self.fn_val, "afterloop");
+                self.builder.build_unconditional_branch(cond_bb).unwrap();
+                self.builder.position_at_end(cond_bb);
+                let cond = self.compile_bool(*stmt.bool)?;
+                self.builder
+                    .build_conditional_branch(cond, loop_bb, after_bb)
+                    .unwrap();
                 self.builder.position_at_end(loop_bb);
-                if let Some(decls) = stmt.decls {
-                    self.compile_decls(*decls)
-                }
-                if let Some(stmts) = stmt.stmts {
-                    self.compile_stmts(*stmts)
-                }
+                self.compile_block(stmt.decls, stmt.stmts)?;
+                self.builder.build_unconditional_branch(cond_bb).unwrap();
+                self.builder.position_at_end(after_bb);
+            }
+            ControlType::DoWhile => {
+                let loop_bb = self.context.append_basic_block(self.fn_val, "loop body");
+                self.builder.build_unconditional_branch(loop_bb).unwrap();
+                self.builder.position_at_end(loop_bb);
+                self.compile_block(stmt.decls, stmt.stmts)?;
                 let after_bb = self.context.append_basic_block(self.fn_val, "afterloop");
-                let cond = self.compile_expr(*stmt.bool).into_int_value();
-                let cond = self
-                    .builder
-                    .build_int_compare(IntPredicate::EQ, cond, zero_const, "if");
+                let cond = self.compile_bool(*stmt.bool)?;
                 self.builder
-                    .build_conditional_branch(cond, after_bb, loop_bb);
+                    .build_conditional_branch(cond, loop_bb, after_bb)
+                    .unwrap();
                 self.builder.position_at_end(after_bb);
             }
         }
+        Ok(())
     }
 
-    fn compile_print(&self, print: PrintStmt) {
-        let e = self.compile_expr(*print.expr);
-        let func_name = match e {
-            BasicMetadataValueEnum::ArrayValue(_) => todo!(),
-            BasicMetadataValueEnum::IntValue(_) => "print_int",
-            BasicMetadataValueEnum::FloatValue(_) => todo!(),
-            BasicMetadataValueEnum::PointerValue(_) => todo!(),
-            BasicMetadataValueEnum::StructValue(_) => todo!(),
-            BasicMetadataValueEnum::VectorValue(_) => todo!(),
-            BasicMetadataValueEnum::MetadataValue(_) => todo!(),
-        };
-        let func = self.module.get_function(func_name).unwrap();
-        let args = vec![e];
-        self.builder.build_call(func, &args, "tmp");
+    /// Compiles the body of an `if`, `else` or loop with a scope of its own.
+    fn compile_block(
+        &mut self,
+        decls: Option<Box<Decls>>,
+        stmts: Option<Box<Stmts>>,
+    ) -> Result<(), Diagnostic> {
+        self.variables.push(HashMap::new());
+        if let Some(decls) = decls {
+            self.compile_decls(*decls)?
+        }
+        if let Some(stmts) = stmts {
+            self.compile_stmts(*stmts)?
+        }
+        self.variables.pop();
+        Ok(())
     }
 
-    fn compile_decls(&mut self, decls: Decls) {
-        self.compile_decl(*decls.decl);
+    fn compile_print(&self, print: PrintStmt) -> Result<(), Diagnostic> {
+        let func_name = format!("print_{}", self.type_of(&print.expr).to_string());
+        let e = self.compile_expr(*print.expr)?;
+        self.call_runtime(&func_name, &[e.into()]);
+        Ok(())
+    }
+
+    pub(super) fn compile_decls(&mut self, decls: Decls) -> Result<(), Diagnostic> {
+        self.compile_decl(*decls.decl)?;
         match decls.decls {
             Some(decls) => self.compile_decls(*decls),
-            None => {}
+            None => Ok(()),
         }
     }
 
-    fn compile_decl(&mut self, decl: Decl) {
-        let ty = match decl.ty {
-            Var::Str => todo!(),
-            Var::Int => self.context.i16_type(),
-            Var::Bool => todo!(),
-        };
+    /// Strings are pointers to nul terminated bytes, like in C. Pointers are
+    /// opaque, so this is the same type as every other pointer.
+    fn str_type(&self) -> PointerType<'ctx> {
+        self.context.ptr_type(AddressSpace::default())
+    }
 
-        let alloc = self.builder.build_alloca(ty, decl.name.as_str());
+    fn int_type(&self, ty: IntTy) -> IntType<'ctx> {
+        self.context.custom_width_int_type(ty.bits())
+    }
 
-        match self.compile_expr(*decl.expr) {
-            BasicMetadataValueEnum::ArrayValue(_) => todo!(),
-            BasicMetadataValueEnum::IntValue(int_val) => {
-                self.builder.build_store(alloc, int_val);
-            }
-            BasicMetadataValueEnum::FloatValue(_) => todo!(),
-            BasicMetadataValueEnum::PointerValue(_) => todo!(),
-            BasicMetadataValueEnum::StructValue(_) => todo!(),
-            BasicMetadataValueEnum::VectorValue(_) => todo!(),
-            BasicMetadataValueEnum::MetadataValue(_) => todo!(),
+    /// The LLVM type values of type `ty` are represented with.
+    pub(super) fn basic_type(&self, ty: &Var) -> BasicTypeEnum<'ctx> {
+        match ty {
+            Var::Int(ty) => self.int_type(*ty).into(),
+            Var::Str => self.str_type().into(),
+            Var::Bool => self.context.bool_type().into(),
         }
-        self.variables.insert(decl.name.to_string(), alloc);
     }
 
-    fn compile_expr(&self, expr: Expr) -> BasicMetadataValueEnum {
+    fn compile_decl(&mut self, decl: Decl) -> Result<(), Diagnostic> {
+        let ty = self.basic_type(&decl.ty);
+
+        let value = self.compile_expr(*decl.expr)?;
+
+        let alloc = self.create_entry_block_alloca(ty, &decl.name);
+        self.builder.build_store(alloc, value).unwrap();
+        self.declare_variable(decl.name, alloc, ty);
+        Ok(())
+    }
+
+    /// Allocates a stack slot at the start of the current function, whatever
+    /// block is being built. Allocas there run once per call rather than once
+    /// per loop iteration, and are what mem2reg promotes into registers.
+    pub(super) fn create_entry_block_alloca(
+        &self,
+        ty: BasicTypeEnum<'ctx>,
+        name: &str,
+    ) -> PointerValue<'ctx> {
+        let builder = self.context.create_builder();
+        let entry = self.fn_val.get_first_basic_block().unwrap();
+        match entry.get_first_instruction() {
+            Some(first) => builder.position_before(&first),
+            None => builder.position_at_end(entry),
+        }
+        builder.build_alloca(ty, name).unwrap()
+    }
+
+    pub(super) fn declare_variable(
+        &mut self,
+        name: String,
+        ptr: PointerValue<'ctx>,
+        ty: BasicTypeEnum<'ctx>,
+    ) {
+        self.variables.last_mut().unwrap().insert(name, (ptr, ty));
+    }
+
+    /// Looks up the stack slot of the innermost variable called `name` and the
+    /// type stored in it.
+    fn variable(&self, name: &str) -> (PointerValue<'ctx>, BasicTypeEnum<'ctx>) {
+        *self
+            .variables
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .expect("the checker resolves every variable")
+    }
+
+    /// The type the checker gave `expr`.
+    fn type_of(&self, expr: &Expr) -> Var {
+        self.types[&expr.span()]
+    }
+
+    pub(super) fn compile_expr(&self, expr: Expr) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
         match expr {
-            Expr::Unary(data) => match data {
-                ExprData::StrLit(_) => todo!(),
-                ExprData::IntLit(int) => self
-                    .context
-                    .i16_type()
-                    .const_int(int.try_into().unwrap(), false)
-                    .into(),
+            Expr::Unary(data, span) => match data {
+                ExprData::StrLit(string) => Ok(self
+                    .builder
+                    .build_global_string_ptr(&string, "str")
+                    .unwrap()
+                    .as_pointer_value()
+                    .into()),
+                // Negative values wrap around to their two's complement bits.
+                ExprData::IntLit(int, _) => Ok(self
+                    .basic_type(&self.types[&span])
+                    .into_int_type()
+                    .const_int(int as u64, false)
+                    .into()),
+                ExprData::BoolLit(b) => {
+                    Ok(self.context.bool_type().const_int(b as u64, false).into())
+                }
                 ExprData::Name(name) => {
-                    let ptr = self.variables.get(name.as_str()).unwrap();
-                    self.builder
-                        .build_load(*ptr, name.as_str())
-                        .into_int_value()
-                        .into()
+                    let (ptr, ty) = self.variable(&name);
+                    Ok(self.builder.build_load(ty, ptr, name.as_str()).unwrap())
                 }
             },
-            Expr::Binary(left, right, op) => self.compile_binary_expr(*left, *right, op),
+            Expr::Binary(left, right, op, span) => {
+                self.compile_binary_expr(*left, *right, op, span)
+            }
+            Expr::Prefix(PrefixOp::Not, operand, _) => {
+                let operand = self.compile_bool(*operand)?;
+                Ok(self.builder.build_not(operand, "not").unwrap().into())
+            }
+            Expr::Prefix(PrefixOp::Neg, operand, span) => {
+                let operand = self.compile_expr(*operand)?.into_int_value();
+                if self.overflow_checks {
+                    // Only negating the most negative value overflows, which
+                    // `0 - x` catches.
+                    let zero = operand.get_type().const_zero();
+                    let message = "attempt to negate with overflow";
+                    Ok(self
+                        .build_checked("llvm.ssub", zero, operand, message, span)
+                        .into())
+                } else {
+                    Ok(self.builder.build_int_neg(operand, "neg").unwrap().into())
+                }
+            }
+            Expr::Index(base, index, span) => {
+                let base = self.compile_expr(*base)?.into_pointer_value();
+                let index = self.compile_expr(*index)?.into_int_value();
+                Ok(self.compile_str_index(base, index, span))
+            }
+            Expr::Call(name, args, _) => Ok(self
+                .compile_call(&name, args)?
+                .expect("the checker rejects using void calls as values")),
         }
     }
 
@@ -216,52 +402,366 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
         left: Expr,
         right: Expr,
         op: Operation,
-    ) -> BasicMetadataValueEnum {
-        let left = self.compile_expr(left);
-        let right = self.compile_expr(right);
-
-        match op {
-            Operation::Equals => {
-                let comp = self.builder.build_int_compare(
-                    inkwell::IntPredicate::EQ,
+        span: Span,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        if let Operation::And | Operation::Or = op {
+            return self.compile_logical_expr(left, right, op);
+        }
+        // Both operands have the same type.
+        let ty = self.type_of(&left);
+        let left = self.compile_expr(left)?;
+        let right = self.compile_expr(right)?;
+
+        match (ty, &op) {
+            (Var::Int(ty), _) => Ok(self.compile_int_op(
+                left.into_int_value(),
+                right.into_int_value(),
+                op,
+                ty,
+                span,
+            )),
+            (Var::Str, Operation::Equals | Operation::Plus) => self.compile_str_op(left, right, op),
+            (Var::Bool, Operation::Equals) => Ok(self
+                .builder
+                .build_int_compare(
+                    IntPredicate::EQ,
                     left.into_int_value(),
                     right.into_int_value(),
                     "tmp",
-                );
-                self.builder
-                    .build_int_cast(comp, self.context.i16_type(), "cast")
+                )
+                .unwrap()
+                .into()),
+            _ => unreachable!("operand types are checked before codegen"),
+        }
+    }
+
+    fn compile_int_op(
+        &self,
+        left: IntValue<'ctx>,
+        right: IntValue<'ctx>,
+        op: Operation,
+        ty: IntTy,
+        span: Span,
+    ) -> BasicValueEnum<'ctx> {
+        let predicate = match (op, ty.is_signed()) {
+            (op @ (Operation::Times | Operation::Plus | Operation::Minus), _)
+                if self.overflow_checks =>
+            {
+                return self.compile_checked_op(left, right, op, ty, span)
+            }
+            (Operation::Times, _) => {
+                return self
+                    .builder
+                    .build_int_mul(left, right, "tmp")
+                    .unwrap()
                     .into()
             }
-            Operation::GT => {
-                let comp = self.builder.build_int_compare(
-                    inkwell::IntPredicate::SGT,
-                    left.into_int_value(),
-                    right.into_int_value(),
-                    "tmp",
-                );
-                self.builder
-                    .build_int_cast(comp, self.context.i16_type(), "cast")
+            (Operation::Plus, _) => {
+                return self
+                    .builder
+                    .build_int_add(left, right, "tmp")
+                    .unwrap()
                     .into()
             }
-            Operation::LT => {
-                let comp = self.builder.build_int_compare(
-                    inkwell::IntPredicate::SLT,
-                    left.into_int_value(),
-                    right.into_int_value(),
-                    "tmp",
-                );
-                self.builder
-                    .build_int_cast(comp, self.context.i16_type(), "cast")
+            (Operation::Minus, _) => {
+                return self
+                    .builder
+                    .build_int_sub(left, right, "tmp")
+                    .unwrap()
                     .into()
             }
-            Operation::Times => self
+            (op @ (Operation::Divide | Operation::Modulo), _) => {
+                return self.compile_division(left, right, op, ty, span)
+            }
+            (Operation::Equals, _) => IntPredicate::EQ,
+            (Operation::GT, true) => IntPredicate::SGT,
+            (Operation::GT, false) => IntPredicate::UGT,
+            (Operation::LT, true) => IntPredicate::SLT,
+            (Operation::LT, false) => IntPredicate::ULT,
+            (Operation::And | Operation::Or, _) => {
+                unreachable!("handled by compile_logical_expr")
+            }
+        };
+        self.builder
+            .build_int_compare(predicate, left, right, "tmp")
+            .unwrap()
+            .into()
+    }
+
+    /// `+`, `-` or `*` that stops the program if the result does not fit in
+    /// `ty`.
+    fn compile_checked_op(
+        &self,
+        left: IntValue<'ctx>,
+        right: IntValue<'ctx>,
+        op: Operation,
+        ty: IntTy,
+        span: Span,
+    ) -> BasicValueEnum<'ctx> {
+        let (name, verb) = match op {
+            Operation::Plus => ("add", "add"),
+            Operation::Minus => ("sub", "subtract"),
+            _ => ("mul", "multiply"),
+        };
+        let sign = if ty.is_signed() { "s" } else { "u" };
+        let intrinsic = format!("llvm.{}{}", sign, name);
+        let message = format!("attempt to {} with overflow", verb);
+        self.build_checked(&intrinsic, left, right, &message, span)
+            .into()
+    }
+
+    /// Calls one of LLVM's `<intrinsic>.with.overflow` arithmetic intrinsics,
+    /// branching to a panic with `message` when it reports an overflow.
+    fn build_checked(
+        &self,
+        intrinsic: &str,
+        left: IntValue<'ctx>,
+        right: IntValue<'ctx>,
+        message: &str,
+        span: Span,
+    ) -> IntValue<'ctx> {
+        let int = left.get_type();
+        // The intrinsics are overloaded by width, e.g. `llvm.sadd.with.overflow.i32`.
+        let name = format!("{}.with.overflow.i{}", intrinsic, int.get_bit_width());
+        let func = self.module.get_function(&name).unwrap_or_else(|| {
+            let ret = self
+                .context
+                .struct_type(&[int.into(), self.context.bool_type().into()], false);
+            self.module
+                .add_function(&name, ret.fn_type(&[int.into(), int.into()], false), None)
+        });
+        let pair = self
+            .builder
+            .build_call(func, &[left.into(), right.into()], "checked")
+            .unwrap()
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_struct_value();
+        let result = self
+            .builder
+            .build_extract_value(pair, 0, "result")
+            .unwrap()
+            .into_int_value();
+        let overflowed = self
+            .builder
+            .build_extract_value(pair, 1, "overflowed")
+            .unwrap()
+            .into_int_value();
+
+        let overflow_bb = self.context.append_basic_block(self.fn_val, "overflow");
+        let ok_bb = self.context.append_basic_block(self.fn_val, "nooverflow");
+        self.builder
+            .build_conditional_branch(overflowed, overflow_bb, ok_bb)
+            .unwrap();
+        self.builder.position_at_end(overflow_bb);
+        self.build_panic(message, span);
+        self.builder.position_at_end(ok_bb);
+        result
+    }
+
+    /// Dividing by zero, or the most negative value of a signed type by -1,
+    /// stops the program with an error instead of being undefined behaviour.
+    fn compile_division(
+        &self,
+        left: IntValue<'ctx>,
+        right: IntValue<'ctx>,
+        op: Operation,
+        ty: IntTy,
+        span: Span,
+    ) -> BasicValueEnum<'ctx> {
+        let zero = right.get_type().const_zero();
+        let is_zero = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, right, zero, "iszero")
+            .unwrap();
+        let zero_bb = self.context.append_basic_block(self.fn_val, "divzero");
+        let ok_bb = self.context.append_basic_block(self.fn_val, "divok");
+        self.builder
+            .build_conditional_branch(is_zero, zero_bb, ok_bb)
+            .unwrap();
+
+        self.builder.position_at_end(zero_bb);
+        let message = match op {
+            Operation::Divide => "attempt to divide by zero",
+            _ => "attempt to calculate the remainder with a divisor of zero",
+        };
+        self.build_panic(message, span);
+
+        self.builder.position_at_end(ok_bb);
+        if ty.is_signed() {
+            let int = left.get_type();
+            let is_min = self
+                .builder
+                .build_int_compare(
+                    IntPredicate::EQ,
+                    left,
+                    int.const_int(ty.min() as u64, false),
+                    "ismin",
+                )
+                .unwrap();
+            let is_minus_one = self
                 .builder
-                .build_int_mul(left.into_int_value(), right.into_int_value(), "tmp")
-                .into(),
-            Operation::Plus => self
+                .build_int_compare(IntPredicate::EQ, right, int.const_all_ones(), "isminusone")
+                .unwrap();
+            let overflows = self
                 .builder
-                .build_int_add(left.into_int_value(), right.into_int_value(), "tmp")
-                .into(),
+                .build_and(is_min, is_minus_one, "overflows")
+                .unwrap();
+            let overflow_bb = self.context.append_basic_block(self.fn_val, "divoverflow");
+            let ok_bb = self
+                .context
+                .append_basic_block(self.fn_val, "divnooverflow");
+            self.builder
+                .build_conditional_branch(overflows, overflow_bb, ok_bb)
+                .unwrap();
+
+            self.builder.position_at_end(overflow_bb);
+            let message = match op {
+                Operation::Divide => "attempt to divide with overflow",
+                _ => "attempt to calculate the remainder with overflow",
+            };
+            self.build_panic(message, span);
+            self.builder.position_at_end(ok_bb);
         }
+        match (op, ty.is_signed()) {
+            (Operation::Divide, true) => self
+                .builder
+                .build_int_signed_div(left, right, "tmp")
+                .unwrap(),
+            (Operation::Divide, false) => self
+                .builder
+                .build_int_unsigned_div(left, right, "tmp")
+                .unwrap(),
+            (_, true) => self
+                .builder
+                .build_int_signed_rem(left, right, "tmp")
+                .unwrap(),
+            (_, false) => self
+                .builder
+                .build_int_unsigned_rem(left, right, "tmp")
+                .unwrap(),
+        }
+        .into()
+    }
+
+    /// Reads the byte at `index` of a string, stopping the program if it is
+    /// out of bounds.
+    fn compile_str_index(
+        &self,
+        string: PointerValue<'ctx>,
+        index: IntValue<'ctx>,
+        span: Span,
+    ) -> BasicValueEnum<'ctx> {
+        let len = self
+            .call_runtime("str_len", &[string.into()])
+            .unwrap()
+            .into_int_value();
+        // An unsigned comparison rejects negative indices too.
+        let in_bounds = self
+            .builder
+            .build_int_compare(IntPredicate::ULT, index, len, "inbounds")
+            .unwrap();
+        let oob_bb = self.context.append_basic_block(self.fn_val, "outofbounds");
+        let ok_bb = self.context.append_basic_block(self.fn_val, "inbounds");
+        self.builder
+            .build_conditional_branch(in_bounds, ok_bb, oob_bb)
+            .unwrap();
+
+        self.builder.position_at_end(oob_bb);
+        self.build_panic("index out of bounds", span);
+
+        self.builder.position_at_end(ok_bb);
+        let byte_type = self.context.i8_type();
+        let ptr = unsafe {
+            self.builder
+                .build_gep(byte_type, string, &[index], "byte")
+                .unwrap()
+        };
+        let byte = self
+            .builder
+            .build_load(byte_type, ptr, "byte")
+            .unwrap()
+            .into_int_value();
+        self.builder
+            .build_int_z_extend(byte, self.int_type(IntTy::I64), "byte")
+            .unwrap()
+            .into()
+    }
+
+    /// Ends the current block with a call that prints `message` along with
+    /// where in the source it happened, then exits the program.
+    fn build_panic(&self, message: &str, span: Span) {
+        let message = format!("{} at line {}, column {}", message, span.line, span.col);
+        let message = self
+            .builder
+            .build_global_string_ptr(&message, "panic_msg")
+            .unwrap()
+            .as_pointer_value();
+        self.call_runtime("runtime_panic", &[message.into()]);
+        self.builder.build_unreachable().unwrap();
+    }
+
+    fn compile_str_op(
+        &self,
+        left: BasicValueEnum<'ctx>,
+        right: BasicValueEnum<'ctx>,
+        op: Operation,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let func_name = match op {
+            Operation::Plus => "str_concat",
+            _ => "str_eq",
+        };
+        Ok(self
+            .call_runtime(func_name, &[left.into(), right.into()])
+            .unwrap())
+    }
+
+    /// `&&` and `||` only evaluate their right operand when the left one
+    /// does not already decide the result.
+    fn compile_logical_expr(
+        &self,
+        left: Expr,
+        right: Expr,
+        op: Operation,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let lhs = self.compile_bool(left)?;
+        let lhs_bb = self.builder.get_insert_block().unwrap();
+        let rhs_bb = self.context.append_basic_block(self.fn_val, "rhs");
+        let merge_bb = self.context.append_basic_block(self.fn_val, "merge");
+        let short_circuit = match op {
+            Operation::And => {
+                self.builder
+                    .build_conditional_branch(lhs, rhs_bb, merge_bb)
+                    .unwrap();
+                false
+            }
+            _ => {
+                self.builder
+                    .build_conditional_branch(lhs, merge_bb, rhs_bb)
+                    .unwrap();
+                true
+            }
+        };
+
+        self.builder.position_at_end(rhs_bb);
+        let rhs = self.compile_bool(right)?;
+        // The right operand may have added blocks of its own.
+        let rhs_bb = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(merge_bb).unwrap();
+
+        self.builder.position_at_end(merge_bb);
+        let bool_type = self.context.bool_type();
+        let phi = self.builder.build_phi(bool_type, "tmp").unwrap();
+        phi.add_incoming(&[
+            (&bool_type.const_int(short_circuit as u64, false), lhs_bb),
+            (&rhs, rhs_bb),
+        ]);
+        Ok(phi.as_basic_value())
+    }
+
+    /// Compiles an expression the checker found to be a `bool`.
+    fn compile_bool(&self, expr: Expr) -> Result<IntValue<'ctx>, Diagnostic> {
+        Ok(self.compile_expr(expr)?.into_int_value())
     }
 }
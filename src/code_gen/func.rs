@@ -0,0 +1,143 @@
+use inkwell::{
+    module::Linkage,
+    types::{BasicMetadataTypeEnum, BasicType},
+    values::BasicMetadataValueEnum,
+    values::BasicValueEnum,
+};
+
+use std::collections::HashMap;
+
+use crate::diagnostic::Diagnostic;
+use crate::parser::{expr::Expr, func::Func, stmts::ReturnStmt};
+
+use super::Compiler;
+
+impl<'a, 'ctx> Compiler<'a, 'ctx> {
+    /// Adds the signature of `func` to the module without compiling its body.
+    /// Only `main` is exported, so user functions never stand in for a
+    /// symbol the runtime or libc links against.
+    pub(super) fn declare_function(&mut self, func: &Func) {
+        let mut param_types: Vec<BasicMetadataTypeEnum> = vec![];
+        for param in &func.params {
+            param_types.push(self.basic_type(&param.ty).into());
+        }
+        let fn_type = match &func.ret {
+            Some(ret) => self.basic_type(ret).fn_type(&param_types, false),
+            None => self.context.void_type().fn_type(&param_types, false),
+        };
+        let fn_val = self
+            .module
+            .add_function(&func.name, fn_type, Some(Linkage::Internal));
+        self.functions.insert(func.name.to_string(), fn_val);
+    }
+
+    pub(super) fn compile_function(&mut self, func: Func) -> Result<(), Diagnostic> {
+        self.fn_val = self.functions[&func.name];
+        self.variables = vec![HashMap::new()];
+        let entry = self.context.append_basic_block(self.fn_val, "entry");
+        self.builder.position_at_end(entry);
+
+        // Parameters get stack slots like any other variable so the body can
+        // assign to them.
+        for (i, param) in func.params.into_iter().enumerate() {
+            let value = self.fn_val.get_nth_param(i as u32).unwrap();
+            let alloc = self.create_entry_block_alloca(value.get_type(), &param.name);
+            self.builder.build_store(alloc, value).unwrap();
+            self.declare_variable(param.name, alloc, value.get_type());
+        }
+
+        if let Some(decls) = func.decls {
+            self.compile_decls(*decls)?
+        }
+        if let Some(stmts) = func.stmts {
+            self.compile_stmts(*stmts)?
+        }
+
+        // The checker made sure functions with a return type always return,
+        // so the only block left open after one is dead.
+        if self.needs_terminator() {
+            match func.ret {
+                None => self.builder.build_return(None).unwrap(),
+                Some(_) => self.builder.build_unreachable().unwrap(),
+            };
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn compile_return(&mut self, stmt: ReturnStmt) -> Result<(), Diagnostic> {
+        match stmt.expr {
+            Some(expr) => {
+                let value = self.compile_expr(*expr)?;
+                self.builder.build_return(Some(&value)).unwrap();
+            }
+            None => self.build_return_void(),
+        }
+        // Anything after the return is dead, but still needs a block to live in.
+        let dead_bb = self.context.append_basic_block(self.fn_val, "afterreturn");
+        self.builder.position_at_end(dead_bb);
+        Ok(())
+    }
+
+    /// Emits a call to a user defined or builtin function, returning its
+    /// result if it has one.
+    pub(super) fn compile_call(
+        &self,
+        name: &str,
+        args: Vec<Expr>,
+    ) -> Result<Option<BasicValueEnum<'ctx>>, Diagnostic> {
+        let func = match self.functions.get(name) {
+            Some(func) => *func,
+            None => {
+                let runtime_name =
+                    builtin(name).expect("unknown functions are rejected by the checker");
+                self.module.get_function(runtime_name).unwrap()
+            }
+        };
+
+        let mut values: Vec<BasicMetadataValueEnum> = vec![];
+        for arg in args {
+            values.push(self.compile_expr(arg)?.into());
+        }
+        // Void calls must not be given a name.
+        let call_name = match func.get_type().get_return_type() {
+            Some(_) => "call",
+            None => "",
+        };
+        Ok(self
+            .builder
+            .build_call(func, &values, call_name)
+            .unwrap()
+            .try_as_basic_value()
+            .left())
+    }
+
+    /// Returns from a function without a return type, or from `main`, which
+    /// returns an exit code of 0.
+    pub(super) fn build_return_void(&self) {
+        match self.fn_val.get_type().get_return_type() {
+            Some(ty) => self
+                .builder
+                .build_return(Some(&ty.into_int_type().const_zero()))
+                .unwrap(),
+            None => self.builder.build_return(None).unwrap(),
+        };
+    }
+
+    /// Whether the block being built is still open, i.e. control can fall
+    /// off its end.
+    pub(super) fn needs_terminator(&self) -> bool {
+        match self.builder.get_insert_block() {
+            Some(block) => block.get_terminator().is_none(),
+            None => false,
+        }
+    }
+}
+
+/// The runtime function implementing a builtin, if `name` is one.
+fn builtin(name: &str) -> Option<&'static str> {
+    match name {
+        "len" => Some("str_len"),
+        _ => None,
+    }
+}
@@ -4,17 +4,36 @@ use inkwell::{
     builder::Builder,
     context::Context,
     module::Module,
-    passes::PassManager,
+    types::BasicTypeEnum,
     values::{FunctionValue, PointerValue},
 };
 
+use crate::diagnostic::Diagnostic;
+use crate::semantic::Types;
+
 mod compile;
+mod func;
+
+/// Why code generation stopped.
+#[derive(Debug)]
+pub enum CompileError {
+    Diagnostic(Diagnostic),
+    /// LLVM rejected the generated code, with what it found wrong.
+    Internal(String),
+}
 
 pub struct Compiler<'a, 'ctx> {
     pub context: &'ctx Context,
+    /// Its methods only fail when it has no insertion point, which it always
+    /// has while compiling, so their results are unwrapped.
     pub builder: &'a Builder<'ctx>,
     pub module: &'a Module<'ctx>,
-    variables: HashMap<String, PointerValue<'ctx>>,
-    pub fpm: &'a PassManager<FunctionValue<'ctx>>,
+    /// Stack slot and type of each variable in scope, one map per block.
+    variables: Vec<HashMap<String, (PointerValue<'ctx>, BasicTypeEnum<'ctx>)>>,
+    functions: HashMap<String, FunctionValue<'ctx>>,
+    /// What the checker found the type of each expression to be.
+    types: Types,
+    /// Whether `+`, `-` and `*` stop the program on overflow.
+    overflow_checks: bool,
     pub fn_val: FunctionValue<'ctx>,
 }
@@ -0,0 +1,64 @@
+use crate::lexer::{Span, SpannedToken};
+
+/// An error tied to a location in the source being compiled.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+        }
+    }
+
+    pub fn expected(expected: &str, found: &SpannedToken) -> Self {
+        Self::new(
+            format!("expected {}, found {}", expected, found.token),
+            found.span,
+        )
+    }
+
+    /// Formats the diagnostic with its location and the offending source line:
+    ///
+    /// ```text
+    /// error: expected `)`, found `{`
+    ///  --> fib.txt:5:13
+    ///   |
+    /// 5 | while (i < 20 {
+    ///   |               ^
+    /// ```
+    pub fn render(&self, file: &str, source: &str) -> String {
+        let line_no = self.span.line.to_string();
+        let gutter = " ".repeat(line_no.len());
+        let line = source.lines().nth(self.span.line - 1).unwrap_or("");
+
+        let width = source
+            .get(self.span.start..self.span.end)
+            .and_then(|text| text.lines().next())
+            .map_or(1, |text| text.chars().count().max(1));
+        let indent: String = line
+            .chars()
+            .take(self.span.col - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        format!(
+            "error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+            self.message,
+            gutter,
+            file,
+            self.span.line,
+            self.span.col,
+            gutter,
+            line_no,
+            line,
+            gutter,
+            indent,
+            "^".repeat(width)
+        )
+    }
+}
@@ -1,10 +1,87 @@
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+extern "C" {
+    fn malloc(size: usize) -> *mut c_char;
+}
+
+/// Defines a `print_<type>` function for each integer type, and keeps them
+/// alive the same way as the functions below.
+macro_rules! print_ints {
+    ($($name:ident: $ty:ty),*) => {
+        $(
+            #[no_mangle]
+            pub extern "C" fn $name(int: $ty) {
+                println!("{}", int);
+            }
+        )*
+
+        #[used]
+        static INT_FNS: ($(extern "C" fn($ty),)*) = ($($name,)*);
+    };
+}
+
+print_ints!(
+    print_i8: i8,
+    print_i16: i16,
+    print_i32: i32,
+    print_i64: i64,
+    print_u8: u8,
+    print_u16: u16,
+    print_u32: u32,
+    print_u64: u64
+);
+
+#[no_mangle]
+pub extern "C" fn print_bool(b: bool) {
+    println!("{}", b);
+}
+
 #[no_mangle]
-pub extern "C" fn print_int(int: i16) {
-    println!("{}", int);
-    return;
+pub unsafe extern "C" fn print_str(s: *const c_char) {
+    println!("{}", CStr::from_ptr(s).to_string_lossy());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn str_eq(a: *const c_char, b: *const c_char) -> bool {
+    CStr::from_ptr(a) == CStr::from_ptr(b)
+}
+
+/// Returns a newly allocated string holding `a` followed by `b`. Nothing
+/// frees strings yet, so the result lives until the program exits.
+#[no_mangle]
+pub unsafe extern "C" fn str_concat(a: *const c_char, b: *const c_char) -> *mut c_char {
+    let a = CStr::from_ptr(a).to_bytes();
+    let b = CStr::from_ptr(b).to_bytes();
+    let out = malloc(a.len() + b.len() + 1);
+    std::ptr::copy_nonoverlapping(a.as_ptr() as *const c_char, out, a.len());
+    std::ptr::copy_nonoverlapping(b.as_ptr() as *const c_char, out.add(a.len()), b.len());
+    *out.add(a.len() + b.len()) = 0;
+    out
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn str_len(s: *const c_char) -> i64 {
+    CStr::from_ptr(s).to_bytes().len() as i64
+}
+
+/// Stops the program after an error it cannot recover from, like dividing by
+/// zero.
+#[no_mangle]
+pub unsafe extern "C" fn runtime_panic(message: *const c_char) -> ! {
+    eprintln!("error: {}", CStr::from_ptr(message).to_string_lossy());
+    std::process::exit(101);
 }
 
 // Adding the functions above to a global array,
 // so Rust compiler won't remove them.
 #[used]
-static EXTERNAL_FNS: [extern "C" fn(i16); 1] = [print_int];
+static BOOL_FNS: [extern "C" fn(bool); 1] = [print_bool];
+#[used]
+static STR_FNS: (
+    unsafe extern "C" fn(*const c_char),
+    unsafe extern "C" fn(*const c_char, *const c_char) -> bool,
+    unsafe extern "C" fn(*const c_char, *const c_char) -> *mut c_char,
+    unsafe extern "C" fn(*const c_char) -> i64,
+    unsafe extern "C" fn(*const c_char) -> !,
+) = (print_str, str_eq, str_concat, str_len, runtime_panic);
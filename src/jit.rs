@@ -0,0 +1,43 @@
+use inkwell::execution_engine::ExecutionEngine;
+use inkwell::module::Module;
+use inkwell::OptimizationLevel;
+
+use crate::io;
+
+/// Compiles `module` in memory and runs its `main`, returning the exit code.
+/// The runtime comes from the copy of `io.rs` built into the compiler, so
+/// nothing is written to disk and no linker is needed.
+pub fn run(module: &Module, level: OptimizationLevel) -> Result<i32, String> {
+    let engine = module
+        .create_jit_execution_engine(level)
+        .map_err(|err| err.to_string())?;
+    map_runtime(module, &engine);
+    let main = module.get_function("main").unwrap();
+    Ok(unsafe { engine.run_function_as_main(main, &[]) })
+}
+
+/// Points the runtime functions `module` declares at the compiler's own
+/// copies of them.
+fn map_runtime(module: &Module, engine: &ExecutionEngine) {
+    let symbols: [(&str, *const ()); 14] = [
+        ("print_i8", io::print_i8 as *const ()),
+        ("print_i16", io::print_i16 as *const ()),
+        ("print_i32", io::print_i32 as *const ()),
+        ("print_i64", io::print_i64 as *const ()),
+        ("print_u8", io::print_u8 as *const ()),
+        ("print_u16", io::print_u16 as *const ()),
+        ("print_u32", io::print_u32 as *const ()),
+        ("print_u64", io::print_u64 as *const ()),
+        ("print_bool", io::print_bool as *const ()),
+        ("print_str", io::print_str as *const ()),
+        ("str_eq", io::str_eq as *const ()),
+        ("str_concat", io::str_concat as *const ()),
+        ("str_len", io::str_len as *const ()),
+        ("runtime_panic", io::runtime_panic as *const ()),
+    ];
+    for (name, addr) in symbols {
+        if let Some(func) = module.get_function(name) {
+            engine.add_global_mapping(&func, addr as usize);
+        }
+    }
+}
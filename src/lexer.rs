@@ -1,17 +1,88 @@
-use std::borrow::Borrow;
+use std::fmt;
 
-use regex::RegexSet;
+use crate::diagnostic::Diagnostic;
 
-use crate::lexer::Token::{
-    Assign, CloseBrace, CloseParen, Equals, If, Ignore, Int, OpenBrace, OpenParen, Print, Semi,
-    Str, WhiteSpace,
-};
+/// The fixed width integer types. `int` is another name for `i64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IntTy {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+}
 
-#[derive(Debug, Clone)]
+impl IntTy {
+    pub const ALL: [IntTy; 8] = [
+        IntTy::I8,
+        IntTy::I16,
+        IntTy::I32,
+        IntTy::I64,
+        IntTy::U8,
+        IntTy::U16,
+        IntTy::U32,
+        IntTy::U64,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        IntTy::ALL.into_iter().find(|ty| ty.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IntTy::I8 => "i8",
+            IntTy::I16 => "i16",
+            IntTy::I32 => "i32",
+            IntTy::I64 => "i64",
+            IntTy::U8 => "u8",
+            IntTy::U16 => "u16",
+            IntTy::U32 => "u32",
+            IntTy::U64 => "u64",
+        }
+    }
+
+    pub fn bits(self) -> u32 {
+        match self {
+            IntTy::I8 | IntTy::U8 => 8,
+            IntTy::I16 | IntTy::U16 => 16,
+            IntTy::I32 | IntTy::U32 => 32,
+            IntTy::I64 | IntTy::U64 => 64,
+        }
+    }
+
+    pub fn is_signed(self) -> bool {
+        matches!(self, IntTy::I8 | IntTy::I16 | IntTy::I32 | IntTy::I64)
+    }
+
+    /// The smallest value of the type.
+    pub fn min(self) -> i128 {
+        if self.is_signed() {
+            -(1 << (self.bits() - 1))
+        } else {
+            0
+        }
+    }
+
+    /// The largest value of the type.
+    pub fn max(self) -> i128 {
+        if self.is_signed() {
+            (1 << (self.bits() - 1)) - 1
+        } else {
+            (1 << self.bits()) - 1
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token {
-    Int,
+    /// `int` or one of the sized integer type names.
+    Int(IntTy),
     Str,
     While,
+    Do,
     OpenParen,
     CloseParen,
     Assign,
@@ -19,10 +90,9 @@ pub enum Token {
     Print,
     OpenBrace,
     CloseBrace,
-    WhiteSpace,
     Semi,
     If,
-    Ignore,
+    Else,
     Times,
     Plus,
     Bool,
@@ -31,159 +101,504 @@ pub enum Token {
     LT,
     Name(String),
     StringLit(String),
-    IntLit(i16),
+    /// The digits of an integer literal and its type suffix, as in `255u8`.
+    /// A leading `-` is a separate token, and whether the value fits its type
+    /// is up to the checker.
+    IntLit(u64, Option<IntTy>),
+    DocComment(String),
+    Fn,
+    Return,
+    Comma,
+    Arrow,
+    BoolLit(bool),
+    And,
+    Or,
+    Not,
+    Minus,
+    Divide,
+    Modulo,
+    OpenBracket,
+    CloseBracket,
 }
 
-#[derive(Debug, Clone)]
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Int(ty) => write!(f, "`{}`", ty.name()),
+            Token::Str => write!(f, "`str`"),
+            Token::While => write!(f, "`while`"),
+            Token::Do => write!(f, "`do`"),
+            Token::OpenParen => write!(f, "`(`"),
+            Token::CloseParen => write!(f, "`)`"),
+            Token::Assign => write!(f, "`=`"),
+            Token::Equals => write!(f, "`==`"),
+            Token::Print => write!(f, "`print`"),
+            Token::OpenBrace => write!(f, "`{{`"),
+            Token::CloseBrace => write!(f, "`}}`"),
+            Token::Semi => write!(f, "`;`"),
+            Token::If => write!(f, "`if`"),
+            Token::Else => write!(f, "`else`"),
+            Token::Times => write!(f, "`*`"),
+            Token::Plus => write!(f, "`+`"),
+            Token::Bool => write!(f, "`bool`"),
+            Token::EOF => write!(f, "end of file"),
+            Token::GT => write!(f, "`>`"),
+            Token::LT => write!(f, "`<`"),
+            Token::Name(name) => write!(f, "`{}`", name),
+            Token::StringLit(string) => write!(f, "{:?}", string),
+            Token::IntLit(int, None) => write!(f, "`{}`", int),
+            Token::IntLit(int, Some(ty)) => write!(f, "`{}{}`", int, ty.name()),
+            Token::DocComment(_) => write!(f, "doc comment"),
+            Token::Fn => write!(f, "`fn`"),
+            Token::Return => write!(f, "`return`"),
+            Token::Comma => write!(f, "`,`"),
+            Token::Arrow => write!(f, "`->`"),
+            Token::BoolLit(b) => write!(f, "`{}`", b),
+            Token::And => write!(f, "`&&`"),
+            Token::Or => write!(f, "`||`"),
+            Token::Not => write!(f, "`!`"),
+            Token::Minus => write!(f, "`-`"),
+            Token::Divide => write!(f, "`/`"),
+            Token::Modulo => write!(f, "`%`"),
+            Token::OpenBracket => write!(f, "`[`"),
+            Token::CloseBracket => write!(f, "`]`"),
+        }
+    }
+}
 
-enum RawToken {
-    Int,
-    Str,
-    While,
-    OpenParen,
-    CloseParen,
-    Assign,
-    Equals,
-    Print,
-    OpenBrace,
-    CloseBrace,
-    WhiteSpace,
-    Semi,
-    If,
-    Ignore,
-    Times,
-    Plus,
-    Bool,
-    EOF,
-    Name,
-    StringLit,
-    IntLit,
-    GT,
-    LT,
+/// Position of a token in the source: the byte range it covers and the
+/// 1-based line and column it starts at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize,
 }
 
-impl RawToken {
-    fn into_token(self, data: &str) -> Token {
-        match self {
-            RawToken::Int => Token::Int,
-            RawToken::Str => Token::Str,
-            RawToken::While => Token::While,
-            RawToken::OpenParen => Token::OpenParen,
-            RawToken::CloseParen => Token::CloseParen,
-            RawToken::Assign => Token::Assign,
-            RawToken::Equals => Token::Equals,
-            RawToken::Print => Token::Print,
-            RawToken::OpenBrace => Token::OpenBrace,
-            RawToken::CloseBrace => Token::CloseBrace,
-            RawToken::WhiteSpace => Token::WhiteSpace,
-            RawToken::Semi => Token::Semi,
-            RawToken::If => Token::If,
-            RawToken::Ignore => Token::Ignore,
-            RawToken::Times => Token::Times,
-            RawToken::Plus => Token::Plus,
-            RawToken::Bool => Token::Bool,
-            RawToken::EOF => Token::EOF,
-            RawToken::GT => Token::GT,
-            RawToken::LT => Token::LT,
-            RawToken::Name => Token::Name(data.to_string()),
-            RawToken::StringLit => Token::StringLit(data.replace("'", "")),
-            RawToken::IntLit => Token::IntLit(data.parse().unwrap()),
+impl Span {
+    /// The span from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
         }
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
 pub enum DataToken {}
 
-pub type Lexeme = Vec<Token>;
+pub type Lexeme = Vec<SpannedToken>;
 
 pub(crate) struct Lexer {
-    match_set: RegexSet,
+    keep_doc_comments: bool,
 }
-const LETTER: &str = r"[A-Za-z]";
-const DIGIT: &str = r"[0-9]";
-const STRING_LIT: &str = r#"^"([^"\\]|\\.)*"$"#;
-const WHITE_SPACE: &str = r"^[ \n\t\r]$";
-
-macro_rules! implement_lexer {
-        ($($pat:expr => $tok:expr), *) => {
-                pub fn new() -> Self {
-                    Self {
-                        match_set: RegexSet::new(&[
-                            $(
-                                $pat,
-                            )*
-                        ])
-                        .unwrap(),
-                    }
-                }
 
-                fn match_token(&self, txt: &str) -> Option<RawToken> {
-                    let matches: Vec<usize> = self.match_set.matches(txt).into_iter().collect();
-                    let m = matches.get(0).unwrap_or(&(1000 as usize));
-                    match m {
-                        $(
-                            ${index()} => Some($tok),
-                        )*
-                        _ => None
-                    }
-                }
+impl Lexer {
+    pub fn new() -> Self {
+        Self {
+            keep_doc_comments: false,
+        }
+    }
+
+    /// Emit `///` comments as `Token::DocComment` instead of discarding them,
+    /// for tools that want to see them. The parser does not accept them.
+    pub fn keep_doc_comments(mut self, keep: bool) -> Self {
+        self.keep_doc_comments = keep;
+        self
+    }
+
+    /// Splits `code` into tokens in a single pass, always taking the longest
+    /// token that starts at the current position. Characters that cannot
+    /// start a token are reported and skipped, so one bad character does not
+    /// hide errors further on.
+    pub fn tokenize(&self, code: &str) -> Result<Lexeme, Vec<Diagnostic>> {
+        let mut scanner = Scanner {
+            code,
+            pos: 0,
+            line: 1,
+            col: 1,
+            errors: vec![],
+            keep_doc_comments: self.keep_doc_comments,
         };
+        let mut lexeme: Vec<SpannedToken> = vec![];
+        while let Some(tok) = scanner.next_token() {
+            lexeme.push(tok);
+        }
+        lexeme.push(SpannedToken {
+            token: Token::EOF,
+            span: Span {
+                start: code.len(),
+                end: code.len(),
+                line: scanner.line,
+                col: scanner.col,
+            },
+        });
+        if scanner.errors.is_empty() {
+            Ok(lexeme)
+        } else {
+            Err(scanner.errors)
+        }
+    }
+}
 
+struct Scanner<'a> {
+    code: &'a str,
+    pos: usize,
+    line: usize,
+    col: usize,
+    errors: Vec<Diagnostic>,
+    keep_doc_comments: bool,
+}
+
+impl<'a> Scanner<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.code.as_bytes().get(self.pos).copied()
     }
 
-impl Lexer {
-    implement_lexer!(
-        r"^int$" => RawToken::Int,
-        r"^str$" => RawToken::Str,
-        r"^bool$" => RawToken::Bool,
-        r"^while$" => RawToken::While,
-        STRING_LIT => RawToken::StringLit,
-        &format!(r"^{}+$", DIGIT) => RawToken::IntLit,
-        r"^\($" => RawToken::OpenParen,
-        r"^\)$" => RawToken::CloseParen,
-        r"^=$" => RawToken::Assign,
-        r"^==$" => RawToken::Equals,
-        r"^>$" => RawToken::GT,
-        r"^<$" => RawToken::LT,
-        r"^print$" => RawToken::Print,
-        r"^\{$"=> RawToken::OpenBrace,
-        r"^}$" => RawToken::CloseBrace,
-        WHITE_SPACE => RawToken::WhiteSpace,
-        r"^;$" => RawToken::Semi,
-        r"^if$" => RawToken::If,
-        r"^\*$" => RawToken::Times,
-        r"^\+$" => RawToken::Plus,
-        &format!("^{}({}|{})*$", LETTER, LETTER, DIGIT) => RawToken::Name
-
-    );
-    pub fn tokenize(&self, code: String) -> Lexeme {
-        let mut lexeme: Vec<Token> = vec![];
-        let mut prev = "".to_string();
-        for char in code.chars() {
-            let new = format!("{}{}", prev, char);
-            match self.match_token(new.as_str()) {
-                None => match self.match_token(prev.as_str()) {
+    fn peek_next(&self) -> Option<u8> {
+        self.code.as_bytes().get(self.pos + 1).copied()
+    }
+
+    fn bump(&mut self) {
+        let byte = self.code.as_bytes()[self.pos];
+        self.pos += 1;
+        if byte == b'\n' {
+            self.line += 1;
+            self.col = 1;
+        } else if byte & 0xC0 != 0x80 {
+            // Only count the first byte of each UTF-8 character.
+            self.col += 1;
+        }
+    }
+
+    /// Consumes a whole, possibly multi-byte, character.
+    fn bump_char(&mut self) -> char {
+        let c = self.code[self.pos..].chars().next().unwrap();
+        for _ in 0..c.len_utf8() {
+            self.bump();
+        }
+        c
+    }
+
+    fn bump_while(&mut self, pred: impl Fn(u8) -> bool) {
+        while matches!(self.peek(), Some(byte) if pred(byte)) {
+            self.bump();
+        }
+    }
+
+    fn next_token(&mut self) -> Option<SpannedToken> {
+        loop {
+            let (start, line, col) = (self.pos, self.line, self.col);
+            let byte = self.peek()?;
+            self.bump();
+            let span = |end| Span {
+                start,
+                end,
+                line,
+                col,
+            };
+            let token = match byte {
+                b' ' | b'\n' | b'\t' | b'\r' => continue,
+                b'(' => Token::OpenParen,
+                b')' => Token::CloseParen,
+                b'{' => Token::OpenBrace,
+                b'}' => Token::CloseBrace,
+                b'[' => Token::OpenBracket,
+                b']' => Token::CloseBracket,
+                b';' => Token::Semi,
+                b',' => Token::Comma,
+                b'-' if self.peek() == Some(b'>') => {
+                    self.bump();
+                    Token::Arrow
+                }
+                b'&' if self.peek() == Some(b'&') => {
+                    self.bump();
+                    Token::And
+                }
+                b'|' if self.peek() == Some(b'|') => {
+                    self.bump();
+                    Token::Or
+                }
+                b'!' => Token::Not,
+                b'-' => Token::Minus,
+                b'*' => Token::Times,
+                b'+' => Token::Plus,
+                b'%' => Token::Modulo,
+                b'>' => Token::GT,
+                b'<' => Token::LT,
+                b'/' if self.peek() == Some(b'/') => {
+                    self.bump();
+                    // `///` starts a doc comment, but `////` is a plain one.
+                    let doc = self.peek() == Some(b'/') && self.peek_next() != Some(b'/');
+                    self.bump_while(|byte| byte != b'\n');
+                    if !(doc && self.keep_doc_comments) {
+                        continue;
+                    }
+                    let text = &self.code[start + 3..self.pos];
+                    Token::DocComment(text.trim_end_matches('\r').to_string())
+                }
+                b'/' if self.peek() == Some(b'*') => {
+                    self.bump();
+                    if !self.block_comment() {
+                        self.errors.push(Diagnostic::new(
+                            "unterminated block comment",
+                            span(start + 2),
+                        ));
+                    }
+                    continue;
+                }
+                b'/' => Token::Divide,
+                b'=' => {
+                    if self.peek() == Some(b'=') {
+                        self.bump();
+                        Token::Equals
+                    } else {
+                        Token::Assign
+                    }
+                }
+                b'"' => match self.string() {
+                    Some(value) => Token::StringLit(value),
                     None => {
-                        prev.push(char);
+                        self.errors.push(Diagnostic::new(
+                            "unterminated string literal",
+                            span(start + 1),
+                        ));
+                        continue;
                     }
-                    Some(tok) => {
-                        match tok {
-                            RawToken::WhiteSpace => (),
-                            _ => lexeme.push(tok.into_token(&prev)),
+                },
+                b'0'..=b'9' => {
+                    self.bump_while(|byte| byte.is_ascii_digit());
+                    let digits_end = self.pos;
+                    self.bump_while(|byte| byte.is_ascii_alphanumeric());
+                    let digits = &self.code[start..digits_end];
+                    let suffix = &self.code[digits_end..self.pos];
+                    let ty = match IntTy::from_name(suffix) {
+                        Some(ty) => Some(ty),
+                        None if suffix.is_empty() => None,
+                        None => {
+                            self.errors.push(Diagnostic::new(
+                                format!("invalid suffix `{}` for integer literal", suffix),
+                                span(self.pos),
+                            ));
+                            continue;
+                        }
+                    };
+                    match digits.parse() {
+                        Ok(int) => Token::IntLit(int, ty),
+                        Err(_) => {
+                            self.errors.push(Diagnostic::new(
+                                format!("integer literal `{}` is too large", digits),
+                                span(self.pos),
+                            ));
+                            continue;
                         }
-                        prev = char.to_string();
                     }
-                },
-                Some(_) => {
-                    prev.push(char);
                 }
+                b'A'..=b'Z' | b'a'..=b'z' => {
+                    self.bump_while(|byte| byte.is_ascii_alphanumeric());
+                    keyword_or_name(&self.code[start..self.pos])
+                }
+                _ => {
+                    // Step over the rest of a multi-byte character.
+                    self.bump_while(|byte| byte & 0xC0 == 0x80);
+                    let char = self.code[start..self.pos].chars().next().unwrap();
+                    self.errors.push(Diagnostic::new(
+                        format!("unexpected character `{}`", char.escape_debug()),
+                        span(self.pos),
+                    ));
+                    continue;
+                }
+            };
+            return Some(SpannedToken {
+                token,
+                span: span(self.pos),
+            });
+        }
+    }
+
+    /// Skips the rest of a block comment after its opening `/*`, returning
+    /// whether it was closed. Block comments nest.
+    fn block_comment(&mut self) -> bool {
+        let mut depth = 1;
+        while depth > 0 {
+            match (self.peek(), self.peek_next()) {
+                (None, _) => return false,
+                (Some(b'/'), Some(b'*')) => {
+                    self.bump();
+                    self.bump();
+                    depth += 1;
+                }
+                (Some(b'*'), Some(b'/')) => {
+                    self.bump();
+                    self.bump();
+                    depth -= 1;
+                }
+                _ => self.bump(),
             }
         }
-        match self.match_token(&prev) {
-            None => {}
-            Some(tok) => lexeme.push(tok.into_token(&prev)),
+        true
+    }
+
+    /// Scans the rest of a string literal after its opening quote, returning
+    /// its contents with escape sequences resolved, or `None` if the file
+    /// ends first.
+    fn string(&mut self) -> Option<String> {
+        let mut value = String::new();
+        loop {
+            let start = self.pos;
+            match self.peek() {
+                None => return None,
+                Some(b'"') => {
+                    self.bump();
+                    return Some(value);
+                }
+                Some(b'\\') => {
+                    let (line, col) = (self.line, self.col);
+                    self.bump();
+                    self.peek()?;
+                    let escaped = match self.bump_char() {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        '\\' => '\\',
+                        '"' => '"',
+                        c => {
+                            let message = match c {
+                                // Strings are C strings, which a nul would cut
+                                // short.
+                                '0' => "strings cannot contain `\\0`".to_string(),
+                                _ => format!("unknown escape sequence `\\{}`", c),
+                            };
+                            self.errors.push(Diagnostic::new(
+                                message,
+                                Span {
+                                    start,
+                                    end: self.pos,
+                                    line,
+                                    col,
+                                },
+                            ));
+                            c
+                        }
+                    };
+                    value.push(escaped);
+                }
+                Some(_) => value.push(self.bump_char()),
+            }
         }
-        lexeme.push(Token::EOF);
-        lexeme
+    }
+}
+
+fn keyword_or_name(word: &str) -> Token {
+    match word {
+        "int" => Token::Int(IntTy::I64),
+        "str" => Token::Str,
+        "bool" => Token::Bool,
+        "while" => Token::While,
+        "do" => Token::Do,
+        "print" => Token::Print,
+        "if" => Token::If,
+        "else" => Token::Else,
+        "fn" => Token::Fn,
+        "return" => Token::Return,
+        "true" => Token::BoolLit(true),
+        "false" => Token::BoolLit(false),
+        _ => match IntTy::from_name(word) {
+            Some(ty) => Token::Int(ty),
+            None => Token::Name(word.to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Lexer, Token};
+
+    /// The tokens in `source`, which must lex, without their spans.
+    fn tokens(lexer: Lexer, source: &str) -> Vec<Token> {
+        lexer
+            .tokenize(source)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token)
+            .collect()
+    }
+
+    /// The errors lexing `source` reports, as `line:col: message`.
+    fn errors(source: &str) -> Vec<String> {
+        match Lexer::new().tokenize(source) {
+            Ok(_) => vec![],
+            Err(errors) => errors
+                .iter()
+                .map(|error| format!("{}:{}: {}", error.span.line, error.span.col, error.message))
+                .collect(),
+        }
+    }
+
+    fn name(name: &str) -> Token {
+        Token::Name(name.to_string())
+    }
+
+    #[test]
+    fn nested_block_comments() {
+        assert_eq!(
+            tokens(Lexer::new(), "a /* x /* y */ z */ b"),
+            [name("a"), name("b"), Token::EOF]
+        );
+    }
+
+    #[test]
+    fn doc_comments() {
+        let source = "/// doc\n//// not doc\n// line\nx";
+        assert_eq!(tokens(Lexer::new(), source), [name("x"), Token::EOF]);
+        assert_eq!(
+            tokens(Lexer::new().keep_doc_comments(true), source),
+            [Token::DocComment(" doc".to_string()), name("x"), Token::EOF]
+        );
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        assert_eq!(
+            errors("a /* x /* y */"),
+            ["1:3: unterminated block comment"]
+        );
+    }
+
+    #[test]
+    fn unterminated_string() {
+        assert_eq!(
+            errors("print(\"abc\n"),
+            ["1:7: unterminated string literal"]
+        );
+    }
+
+    #[test]
+    fn invalid_integer_suffixes() {
+        assert_eq!(
+            errors("255u7 1x 2i8"),
+            [
+                "1:1: invalid suffix `u7` for integer literal",
+                "1:7: invalid suffix `x` for integer literal",
+            ]
+        );
+    }
+
+    #[test]
+    fn continues_after_unexpected_character() {
+        assert_eq!(
+            errors("a @ b # c"),
+            [
+                "1:3: unexpected character `@`",
+                "1:7: unexpected character `#`",
+            ]
+        );
     }
 }
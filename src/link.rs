@@ -0,0 +1,118 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use crate::cli::Options;
+
+/// The runtime library built from `io.rs` by `build.rs`. It is part of the
+/// compiler binary, so `crabby` works from any directory.
+static RUNTIME: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/libcrabby_rt.a"));
+
+/// Tried in order when neither `--linker` nor `$CC` says what to use. Going
+/// through a C compiler driver instead of calling `ld` directly means it
+/// passes the platform's crt startup objects and libc for us.
+const DEFAULT_LINKERS: [&str; 3] = ["cc", "clang", "gcc"];
+
+/// The system libraries the runtime, a Rust staticlib, needs, as rustc
+/// listed them when `build.rs` built it.
+static RUNTIME_LIBS: &str = include_str!(concat!(env!("OUT_DIR"), "/libcrabby_rt.libs"));
+
+/// Links `object`, the contents of an object file, and the runtime library
+/// into an executable at `output`, along with any libraries given on the
+/// command line.
+pub fn link(opts: &Options, object: &[u8], output: &Path) -> Result<(), String> {
+    // The linker needs both as files, so they get temporary ones.
+    let object = write_temp_file("main.o", object)
+        .map_err(|err| format!("could not write the object file: {}", err))?;
+    let linked = match write_temp_file("libcrabby_rt.a", RUNTIME) {
+        Ok(runtime) => {
+            let linked = run_linker(opts, &object, &runtime, output);
+            let _ = fs::remove_file(&runtime);
+            linked
+        }
+        Err(err) => Err(format!("could not write the runtime library: {}", err)),
+    };
+    let _ = fs::remove_file(&object);
+    linked
+}
+
+/// Writes `contents` to a new file in the temporary directory, whose name
+/// ends in `name`, and returns its path. The file is always newly created
+/// and only readable by us, so nothing another user left at the same name,
+/// like a symlink, is ever written through.
+fn write_temp_file(name: &str, contents: &[u8]) -> io::Result<PathBuf> {
+    let mut attempt = 0;
+    loop {
+        let path = env::temp_dir().join(format!("crabby-{}-{}-{}", process::id(), attempt, name));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        match options.open(&path) {
+            Ok(mut file) => {
+                if let Err(err) = file.write_all(contents) {
+                    let _ = fs::remove_file(&path);
+                    return Err(err);
+                }
+                return Ok(path);
+            }
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => attempt += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn run_linker(opts: &Options, object: &Path, runtime: &Path, output: &Path) -> Result<(), String> {
+    let linkers: Vec<OsString> = match (&opts.linker, env::var_os("CC")) {
+        (Some(linker), _) => vec![linker.clone().into_os_string()],
+        (None, Some(cc)) => vec![cc],
+        (None, None) => DEFAULT_LINKERS.iter().map(OsString::from).collect(),
+    };
+    for linker in &linkers {
+        let mut cmd = Command::new(linker);
+        cmd.arg(object).arg(runtime);
+        for dir in &opts.lib_dirs {
+            cmd.arg("-L").arg(dir);
+        }
+        for lib in &opts.libs {
+            cmd.arg(format!("-l{}", lib));
+        }
+        cmd.args(RUNTIME_LIBS.split_whitespace())
+            .arg("-o")
+            .arg(output);
+
+        match cmd.status() {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => {
+                return Err(format!(
+                    "linking with `{}` failed: {}",
+                    linker.to_string_lossy(),
+                    status
+                ))
+            }
+            // Not installed, so fall back to the next one.
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(format!(
+                    "could not run linker `{}`: {}",
+                    linker.to_string_lossy(),
+                    err
+                ))
+            }
+        }
+    }
+
+    let tried: Vec<String> = linkers
+        .iter()
+        .map(|linker| format!("`{}`", linker.to_string_lossy()))
+        .collect();
+    Err(format!(
+        "could not find a linker, tried {}; pass one with --linker=<path>",
+        tried.join(", ")
+    ))
+}
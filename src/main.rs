@@ -1,92 +1,208 @@
-#![feature(macro_metavar_expr)]
 extern crate core;
-use std::ffi::CString;
-use std::fs;
-use std::process::Command;
-
-use code_gen::Compiler;
-use inkwell::passes::PassManager;
-use inkwell::targets::{CodeModel, InitializationConfig, RelocMode, Target};
-use inkwell::OptimizationLevel;
-use inkwell::{context::Context, targets::TargetMachine};
-use libc::c_void;
-use llvm_sys::support::LLVMAddSymbol;
-
-use llvm_sys::target_machine;
+use std::path::Path;
+use std::process;
+use std::{env, fs};
+
+use cli::{Emit, Options};
+
+use code_gen::{CompileError, Compiler};
+use diagnostic::Diagnostic;
+use inkwell::targets::{
+    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetTriple,
+};
+use inkwell::{context::Context, module::Module, targets::TargetMachine};
+
 use parser::program::Program;
+use semantic::Checker;
 
 use crate::lexer::{Lexeme, Token};
 
+mod cli;
 mod code_gen;
+mod diagnostic;
 mod io;
+mod jit;
 mod lexer;
+mod link;
+mod opt;
 mod parser;
+mod semantic;
 
 fn main() {
-    let lexer = lexer::Lexer::new();
-    let contents = fs::read_to_string("fib.txt").unwrap();
-    let lexeme = lexer.tokenize(contents);
+    let opts = match Options::parse(env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, cli::USAGE);
+            process::exit(2);
+        }
+    };
+
+    let contents = match fs::read_to_string(&opts.input) {
+        Ok(contents) => contents,
+        Err(err) => {
+            eprintln!("error: could not read {}: {}", opts.input.display(), err);
+            process::exit(1);
+        }
+    };
+
+    // Doc comments are only of interest to someone reading the token stream.
+    let lexer = lexer::Lexer::new().keep_doc_comments(opts.emit == Emit::Tokens);
+    let lexeme = match lexer.tokenize(&contents) {
+        Ok(lexeme) => lexeme,
+        Err(errors) => report_errors(&opts, &contents, &errors),
+    };
+    if opts.emit == Emit::Tokens {
+        let tokens: String = lexeme.iter().map(|tok| format!("{:?}\n", tok)).collect();
+        write_output(&opts, &tokens);
+        return;
+    }
+
     let mut lex_p = 0;
-    let p = Program::new(&lexeme, &mut lex_p);
+    let p = match Program::new(&lexeme, &mut lex_p) {
+        Ok(p) => p,
+        Err(errors) => report_errors(&opts, &contents, &errors),
+    };
+    if opts.emit == Emit::Ast {
+        write_output(&opts, &(p.to_string() + "\n"));
+        return;
+    }
+    let types = match Checker::check(&p) {
+        Ok(types) => types,
+        Err(errors) => report_errors(&opts, &contents, &errors),
+    };
+
+    Target::initialize_all(&InitializationConfig::default());
+
+    let target_triple = match &opts.target {
+        Some(triple) => TargetTriple::create(triple),
+        None => TargetMachine::get_default_triple(),
+    };
+    let target = match Target::from_triple(&target_triple) {
+        Ok(target) => target,
+        Err(err) => {
+            eprintln!("error: unknown target `{}`: {}", target_triple, err);
+            process::exit(1);
+        }
+    };
+    let target_machine = match target.create_target_machine(
+        &target_triple,
+        &opts.cpu,
+        &opts.features,
+        opt::codegen_level(opts.opt_level),
+        RelocMode::Default,
+        CodeModel::Default,
+    ) {
+        Some(target_machine) => target_machine,
+        None => {
+            eprintln!(
+                "error: could not generate code for `{}` with cpu `{}`",
+                target_triple, opts.cpu
+            );
+            process::exit(1);
+        }
+    };
 
     let context = Context::create();
     let builder = context.create_builder();
     let module = context.create_module("main");
+    // Emitted IR and bitcode describe the target they were generated for.
+    module.set_triple(&target_triple);
+    module.set_data_layout(&target_machine.get_target_data().get_data_layout());
+
+    if let Err(err) = Compiler::compile(
+        &context,
+        &builder,
+        &module,
+        p,
+        types,
+        opts.overflow_checks(),
+    ) {
+        match err {
+            CompileError::Diagnostic(err) => report_errors(&opts, &contents, &[err]),
+            CompileError::Internal(message) => {
+                eprintln!("error: internal compiler error: {}", message);
+                process::exit(1);
+            }
+        }
+    }
+    if let Err(err) = opt::optimize(&module, &target_machine, &opts) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
 
-    let fpm = PassManager::create(&module);
-
-    fpm.add_instruction_combining_pass();
-    fpm.add_reassociate_pass();
-    fpm.add_gvn_pass();
-    fpm.add_cfg_simplification_pass();
-    fpm.add_basic_alias_analysis_pass();
-    fpm.add_promote_memory_to_register_pass();
-    fpm.add_instruction_combining_pass();
-    fpm.add_reassociate_pass();
-
-    fpm.initialize();
-
-    let int_type = context.i16_type();
-    let fn_type = context
-        .void_type()
-        .fn_type(vec![int_type.into()].as_slice(), false);
-
-    module.add_function("print_int", fn_type, None);
-
-    println!("{}", p.to_string());
+    if opts.command == cli::Command::Run {
+        match jit::run(&module, opt::codegen_level(opts.opt_level)) {
+            Ok(code) => process::exit(code),
+            Err(err) => {
+                eprintln!("error: could not run {}: {}", opts.input.display(), err);
+                process::exit(1);
+            }
+        }
+    }
 
-    Compiler::compile(&context, &builder, &module, &fpm, p);
+    let output = opts.output_path().unwrap();
+    match opts.emit {
+        Emit::LlvmIr => write_output(&opts, &module.print_to_string().to_string()),
+        Emit::LlvmBc => {
+            if !module.write_bitcode_to_path(&output) {
+                eprintln!("error: could not write {}", output.display());
+                process::exit(1);
+            }
+        }
+        Emit::Asm => write_machine_code(&target_machine, &module, FileType::Assembly, &output),
+        Emit::Obj => write_machine_code(&target_machine, &module, FileType::Object, &output),
+        Emit::Exe => {
+            let object = match target_machine.write_to_memory_buffer(&module, FileType::Object) {
+                Ok(object) => object,
+                Err(err) => {
+                    eprintln!("error: could not generate code: {}", err);
+                    process::exit(1);
+                }
+            };
+            if let Err(err) = link::link(&opts, object.as_slice(), &output) {
+                eprintln!("error: {}", err);
+                process::exit(1);
+            }
+        }
+        Emit::Tokens | Emit::Ast => unreachable!(),
+    }
+}
 
-    unsafe {
-        let c_str = CString::new(b"print_int" as &[u8]).unwrap();
-        LLVMAddSymbol(c_str.as_ptr(), io::print_int as *mut c_void)
+/// Prints every diagnostic against the source it came from and exits.
+fn report_errors(opts: &Options, source: &str, errors: &[Diagnostic]) -> ! {
+    let file = opts.input.display().to_string();
+    for error in errors {
+        eprintln!("{}\n", error.render(&file, source));
     }
+    eprintln!(
+        "error: could not compile {} due to {} previous error(s)",
+        file,
+        errors.len()
+    );
+    process::exit(1);
+}
 
-    Target::initialize_all(&InitializationConfig::default());
+/// Writes textual output to the requested file, or stdout if there is none.
+fn write_output(opts: &Options, text: &str) {
+    match opts.output_path() {
+        Some(path) => {
+            if let Err(err) = fs::write(&path, text) {
+                eprintln!("error: could not write {}: {}", path.display(), err);
+                process::exit(1);
+            }
+        }
+        None => print!("{}", text),
+    }
+}
 
-    let target_triple = TargetMachine::get_default_triple();
-    let target = Target::from_triple(&target_triple).unwrap();
-    let target_machine = target
-        .create_target_machine(
-            &target_triple,
-            "generic",
-            "",
-            OptimizationLevel::Default,
-            RelocMode::Default,
-            CodeModel::Default,
-        )
-        .unwrap();
-    let output_filename = "object_file";
-    target_machine
-        .write_to_file(
-            &module,
-            inkwell::targets::FileType::Object,
-            output_filename.as_ref(),
-        )
-        .unwrap();
-
-    Command::new("clang")
-        .args(&["object_file", "libio.a"])
-        .status()
-        .unwrap();
+fn write_machine_code(
+    target_machine: &TargetMachine,
+    module: &Module,
+    file_type: FileType,
+    path: &Path,
+) {
+    if let Err(err) = target_machine.write_to_file(module, file_type, path) {
+        eprintln!("error: could not write {}: {}", path.display(), err);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,45 @@
+use inkwell::module::Module;
+use inkwell::passes::PassBuilderOptions;
+use inkwell::targets::TargetMachine;
+use inkwell::OptimizationLevel;
+
+use crate::cli::{OptLevel, Options};
+
+/// Optimizes `module` in place, with the pipeline given by `--passes` if
+/// there is one and the standard pipeline for the optimization level
+/// otherwise.
+pub fn optimize(
+    module: &Module,
+    target_machine: &TargetMachine,
+    opts: &Options,
+) -> Result<(), String> {
+    let pipeline = match &opts.passes {
+        Some(pipeline) => pipeline.as_str(),
+        None => standard_pipeline(opts.opt_level),
+    };
+    module
+        .run_passes(pipeline, target_machine, PassBuilderOptions::create())
+        .map_err(|err| format!("could not run passes `{}`: {}", pipeline, err))
+}
+
+/// The level the target machine generates code at.
+pub fn codegen_level(level: OptLevel) -> OptimizationLevel {
+    match level {
+        OptLevel::O0 => OptimizationLevel::None,
+        OptLevel::O1 => OptimizationLevel::Less,
+        OptLevel::O2 | OptLevel::Os => OptimizationLevel::Default,
+        OptLevel::O3 => OptimizationLevel::Aggressive,
+    }
+}
+
+/// The same pipelines clang uses for each level. `default<O0>` only runs
+/// the passes that have to run, like `always-inline`.
+fn standard_pipeline(level: OptLevel) -> &'static str {
+    match level {
+        OptLevel::O0 => "default<O0>",
+        OptLevel::O1 => "default<O1>",
+        OptLevel::O2 => "default<O2>",
+        OptLevel::O3 => "default<O3>",
+        OptLevel::Os => "default<Os>",
+    }
+}
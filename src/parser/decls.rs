@@ -1,4 +1,7 @@
+use crate::diagnostic::Diagnostic;
+use crate::lexer::Span;
 use crate::parser::var::Var;
+use crate::parser::{expect, span_from};
 use crate::{Lexeme, Token};
 
 use super::expr::Expr;
@@ -7,6 +10,7 @@ pub(crate) struct Decl {
     pub(crate) ty: Var,
     pub(crate) name: String,
     pub(crate) expr: Box<Expr>,
+    pub(crate) span: Span,
 }
 
 pub(crate) struct Decls {
@@ -15,59 +19,39 @@ pub(crate) struct Decls {
 }
 
 impl Decl {
-    pub fn new(lex: &Lexeme, x: &mut usize) -> Self {
-        let ty = match lex[*x] {
-            Token::Int => Var::Int,
-            Token::Str => Var::Str,
-            Token::Bool => Var::Bool,
-            _ => {
-                panic!("syntax error")
-            }
-        };
-        *x += 1;
-        let name = match &lex[*x] {
+    pub fn new(lex: &Lexeme, x: &mut usize) -> Result<Self, Diagnostic> {
+        let start = *x;
+        let ty = Var::parse(lex, x)?;
+        let name = match &lex[*x].token {
             Token::Name(str) => str,
-            _ => {
-                panic!("syntax error")
-            }
+            _ => return Err(Diagnostic::expected("a variable name", &lex[*x])),
         };
         *x += 1;
-        match &lex[*x] {
-            Token::Assign => {}
-            _ => {
-                panic!("syntax error")
-            }
-        }
-        *x += 1;
-        let e = Expr::new(lex, x);
-        match e {
-            None => {
-                println!("{:?}", lex[*x]);
-                panic!("syntax error when parsing declaration expression")
-            }
-            Some(e) => Self {
-                ty,
-                name: name.to_owned(),
-                expr: e,
-            },
-        }
+        expect(lex, x, Token::Assign)?;
+        let e = Expr::new(lex, x)?;
+        Ok(Self {
+            ty,
+            name: name.to_owned(),
+            expr: e,
+            span: span_from(lex, start, *x),
+        })
     }
 }
 
 impl Decls {
-    pub fn new(lex: &Lexeme, mut x: &mut usize) -> Option<Box<Self>> {
+    pub fn new(lex: &Lexeme, x: &mut usize) -> Result<Option<Box<Self>>, Diagnostic> {
         match lex.get(*x) {
-            None => None,
-            Some(tok) => match tok {
-                Token::Str | Token::Int | Token::Bool => {
-                    let d = Decl::new(lex, x);
-                    let ds = Decls::new(lex, x);
-                    Some(Box::from(Self {
+            None => Ok(None),
+            Some(tok) => match tok.token {
+                Token::Str | Token::Int(_) | Token::Bool => {
+                    let d = Decl::new(lex, x)?;
+                    let ds = Decls::new(lex, x)?;
+                    Ok(Some(Box::from(Self {
                         decl: Box::from(d),
                         decls: ds,
-                    }))
+                    })))
                 }
-                _ => None,
+                _ => Ok(None),
             },
         }
     }
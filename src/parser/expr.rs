@@ -1,5 +1,7 @@
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{IntTy, Span};
+use crate::parser::{expect, span_from};
 use crate::{Lexeme, Token};
-use std::{fmt::format, fs::OpenOptions};
 
 pub enum Operation {
     Equals,
@@ -7,138 +9,230 @@ pub enum Operation {
     Plus,
     GT,
     LT,
+    And,
+    Or,
+    Minus,
+    Divide,
+    Modulo,
+}
+
+pub enum PrefixOp {
+    Not,
+    Neg,
 }
 
 #[derive(Debug)]
 pub enum ExprData {
     StrLit(String),
-    IntLit(i16),
+    /// The value and the type suffix, if the literal was written with one.
+    IntLit(i128, Option<IntTy>),
+    BoolLit(bool),
     Name(String),
 }
 
 pub enum Expr {
-    Unary(ExprData),
-    Binary(Box<Expr>, Box<Expr>, Operation),
+    Unary(ExprData, Span),
+    Binary(Box<Expr>, Box<Expr>, Operation, Span),
+    Call(String, Vec<Expr>, Span),
+    Prefix(PrefixOp, Box<Expr>, Span),
+    /// `base[index]`.
+    Index(Box<Expr>, Box<Expr>, Span),
 }
 
-impl Expr {
-    pub(crate) fn new(lex: &Lexeme, mut x: &mut usize) -> Option<Box<Self>> {
-        let save = x.clone();
-        let mut expr_stack: Vec<Expr> = vec![];
-        let mut op_stack: Vec<Operation> = vec![];
-        let mut get_op = false;
-        loop {
-            match &lex[*x] {
-                Token::StringLit(string) => {
-                    if get_op {
-                        break;
-                    }
-                    expr_stack.push(Expr::Unary(ExprData::StrLit(string.to_string())))
-                }
-                Token::IntLit(int) => {
-                    if get_op {
-                        break;
-                    }
-                    expr_stack.push(Expr::Unary(ExprData::IntLit(*int)))
-                }
-
-                Token::Name(name) => {
-                    if get_op {
-                        break;
-                    }
-                    expr_stack.push(Expr::Unary(ExprData::Name(name.to_string())))
-                }
-                Token::Times => {
-                    if !get_op {
-                        break;
-                    }
-                    perform_operation(&mut expr_stack, &mut op_stack, lex[*x].clone().into())
-                }
-                Token::Equals => {
-                    if !get_op {
-                        break;
-                    }
-                    perform_operation(&mut expr_stack, &mut op_stack, lex[*x].clone().into())
-                }
-                Token::Plus => {
-                    if !get_op {
-                        break;
-                    }
-                    perform_operation(&mut expr_stack, &mut op_stack, lex[*x].clone().into())
-                }
-                Token::GT => {
-                    if !get_op {
-                        break;
-                    }
-                    perform_operation(&mut expr_stack, &mut op_stack, lex[*x].clone().into())
-                }
-                Token::LT => {
-                    if !get_op {
-                        break;
-                    }
-                    perform_operation(&mut expr_stack, &mut op_stack, lex[*x].clone().into())
-                }
-                _ => {
-                    break;
-                }
-            }
-            get_op = !get_op;
-            *x += 1
-        }
-
-        while op_stack.len() > 0 {
-            combine_expression(&mut expr_stack, &mut op_stack)
-        }
-
-        return Some(Box::new(expr_stack.pop().unwrap()));
-    }
+#[derive(PartialEq, Eq)]
+enum Assoc {
+    Left,
+    /// `a < b < c` is an error rather than meaning `(a < b) < c`.
+    None,
 }
 
-impl From<Token> for Operation {
-    fn from(tok: Token) -> Self {
-        match tok {
+impl Operation {
+    fn from_token(tok: &Token) -> Option<Self> {
+        Some(match tok {
             Token::Times => Operation::Times,
             Token::Plus => Operation::Plus,
             Token::Equals => Operation::Equals,
             Token::GT => Operation::GT,
             Token::LT => Operation::LT,
-            _ => panic!("invalid operator"),
+            Token::And => Operation::And,
+            Token::Or => Operation::Or,
+            Token::Minus => Operation::Minus,
+            Token::Divide => Operation::Divide,
+            Token::Modulo => Operation::Modulo,
+            _ => return None,
+        })
+    }
+
+    /// How tightly the operator binds, higher first, and which way a chain of
+    /// operators with the same precedence groups.
+    fn binding(&self) -> (u8, Assoc) {
+        match self {
+            Operation::Times | Operation::Divide | Operation::Modulo => (5, Assoc::Left),
+            Operation::Plus | Operation::Minus => (4, Assoc::Left),
+            Operation::Equals | Operation::GT | Operation::LT => (3, Assoc::None),
+            Operation::And => (2, Assoc::Left),
+            Operation::Or => (1, Assoc::Left),
+        }
+    }
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Unary(_, span) => *span,
+            Expr::Binary(_, _, _, span) => *span,
+            Expr::Call(_, _, span) => *span,
+            Expr::Prefix(_, _, span) => *span,
+            Expr::Index(_, _, span) => *span,
         }
     }
+
+    pub(crate) fn new(lex: &Lexeme, x: &mut usize) -> Result<Box<Self>, Diagnostic> {
+        Ok(Box::new(parse_binary(lex, x, 0)?))
+    }
+}
+
+/// Whether `tok` can be the first token of an expression.
+pub(crate) fn starts_expr(tok: &Token) -> bool {
+    matches!(
+        tok,
+        Token::Name(_)
+            | Token::IntLit(..)
+            | Token::StringLit(_)
+            | Token::BoolLit(_)
+            | Token::Not
+            | Token::Minus
+            | Token::OpenParen
+    )
 }
 
-fn get_priority(op: &Operation) -> u8 {
-    match op {
-        Operation::Times => 3,
-        Operation::Plus => 2,
-        Operation::Equals => 1,
-        Operation::GT => 1,
-        Operation::LT => 1,
-        _ => panic!("invalid operator"),
+/// Precedence climbing: parses an operand, then keeps folding in binary
+/// operators that bind at least as tightly as `min_prec`. The right operand
+/// of each is parsed with a higher minimum, so `a - b - c` groups as
+/// `(a - b) - c`.
+fn parse_binary(lex: &Lexeme, x: &mut usize, min_prec: u8) -> Result<Expr, Diagnostic> {
+    let mut left = parse_prefix(lex, x)?;
+    while let Some(op) = Operation::from_token(&lex[*x].token) {
+        let (prec, assoc) = op.binding();
+        if prec < min_prec {
+            break;
+        }
+        *x += 1;
+        let right = parse_binary(lex, x, prec + 1)?;
+        if assoc == Assoc::None {
+            if let Some(next) = Operation::from_token(&lex[*x].token) {
+                if next.binding().0 == prec {
+                    return Err(Diagnostic::new(
+                        "comparison operators cannot be chained",
+                        lex[*x].span,
+                    ));
+                }
+            }
+        }
+        let span = left.span().to(right.span());
+        left = Expr::Binary(Box::new(left), Box::new(right), op, span);
     }
+    Ok(left)
 }
 
-fn perform_operation(expr_stack: &mut Vec<Expr>, op_stack: &mut Vec<Operation>, tok: Operation) {
-    let oper: Operation = tok.into();
-    while op_stack.len() >= 1 && get_priority(&oper) <= get_priority(&*op_stack.last().unwrap()) {
-        combine_expression(expr_stack, op_stack)
+/// Parses `!` and `-` applied to an operand. They bind tighter than any
+/// binary operator but looser than calls and indexing, so `-s[0]` negates
+/// the indexed value.
+fn parse_prefix(lex: &Lexeme, x: &mut usize) -> Result<Expr, Diagnostic> {
+    let start = lex[*x].span;
+    let op = match lex[*x].token {
+        Token::Not => PrefixOp::Not,
+        // A `-` right before a literal is part of it, which is the only way
+        // to write the most negative value of a type.
+        Token::Minus => match lex[*x + 1].token {
+            Token::IntLit(int, ty) => {
+                let span = start.to(lex[*x + 1].span);
+                *x += 2;
+                let literal = ExprData::IntLit(-(int as i128), ty);
+                return parse_postfix(lex, x, Expr::Unary(literal, span));
+            }
+            _ => PrefixOp::Neg,
+        },
+        _ => {
+            let primary = parse_primary(lex, x)?;
+            return parse_postfix(lex, x, primary);
+        }
+    };
+    *x += 1;
+    let operand = parse_prefix(lex, x)?;
+    let span = start.to(operand.span());
+    Ok(Expr::Prefix(op, Box::new(operand), span))
+}
+
+/// Applies any indexing that follows `base`.
+fn parse_postfix(lex: &Lexeme, x: &mut usize, mut base: Expr) -> Result<Expr, Diagnostic> {
+    while let Token::OpenBracket = lex[*x].token {
+        *x += 1;
+        let index = parse_binary(lex, x, 0)?;
+        let end = lex[*x].span;
+        expect(lex, x, Token::CloseBracket)?;
+        let span = base.span().to(end);
+        base = Expr::Index(Box::new(base), Box::new(index), span);
     }
-    op_stack.push(oper)
+    Ok(base)
+}
+
+fn parse_primary(lex: &Lexeme, x: &mut usize) -> Result<Expr, Diagnostic> {
+    let tok = &lex[*x];
+    let expr = match &tok.token {
+        Token::StringLit(string) => Expr::Unary(ExprData::StrLit(string.to_string()), tok.span),
+        Token::IntLit(int, ty) => Expr::Unary(ExprData::IntLit(*int as i128, *ty), tok.span),
+        Token::BoolLit(b) => Expr::Unary(ExprData::BoolLit(*b), tok.span),
+        Token::Name(name) => {
+            if let Token::OpenParen = lex[*x + 1].token {
+                let start = *x;
+                *x += 1;
+                let args = parse_args(lex, x)?;
+                return Ok(Expr::Call(
+                    name.to_string(),
+                    args,
+                    span_from(lex, start, *x),
+                ));
+            }
+            Expr::Unary(ExprData::Name(name.to_string()), tok.span)
+        }
+        Token::OpenParen => {
+            *x += 1;
+            let inner = parse_binary(lex, x, 0)?;
+            expect(lex, x, Token::CloseParen)?;
+            return Ok(inner);
+        }
+        _ => return Err(Diagnostic::expected("an expression", tok)),
+    };
+    *x += 1;
+    Ok(expr)
 }
 
-fn combine_expression(expr_stack: &mut Vec<Expr>, op_stack: &mut Vec<Operation>) {
-    let op = op_stack.pop().unwrap();
-    let operand2 = expr_stack.pop().unwrap();
-    let operand1 = expr_stack.pop().unwrap();
-    let expr = Expr::Binary(Box::new(operand1), Box::new(operand2), op);
-    expr_stack.push(expr)
+/// Parses a parenthesised, comma separated argument list.
+pub(crate) fn parse_args(lex: &Lexeme, x: &mut usize) -> Result<Vec<Expr>, Diagnostic> {
+    expect(lex, x, Token::OpenParen)?;
+    let mut args = vec![];
+    if let Token::CloseParen = lex[*x].token {
+        *x += 1;
+        return Ok(args);
+    }
+    loop {
+        args.push(*Expr::new(lex, x)?);
+        match lex[*x].token {
+            Token::Comma => *x += 1,
+            _ => {
+                expect(lex, x, Token::CloseParen)?;
+                return Ok(args);
+            }
+        }
+    }
 }
 
 impl ToString for Expr {
     fn to_string(&self) -> String {
         match self {
-            Expr::Unary(data) => format!("{:?}", data),
-            Expr::Binary(oper1, oper2, op) => {
+            Expr::Unary(data, _) => format!("{:?}", data),
+            Expr::Binary(oper1, oper2, op, _) => {
                 format!(
                     "({} {} {})",
                     oper1.to_string(),
@@ -146,6 +240,12 @@ impl ToString for Expr {
                     oper2.to_string()
                 )
             }
+            Expr::Call(name, args, _) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                format!("{}({})", name, args.join(", "))
+            }
+            Expr::Prefix(op, operand, _) => format!("({}{})", op.to_string(), operand.to_string()),
+            Expr::Index(base, index, _) => format!("{}[{}]", base.to_string(), index.to_string()),
         }
     }
 }
@@ -158,6 +258,90 @@ impl ToString for Operation {
             Operation::Plus => "+".to_owned(),
             Operation::GT => ">".to_owned(),
             Operation::LT => "<".to_owned(),
+            Operation::And => "&&".to_owned(),
+            Operation::Or => "||".to_owned(),
+            Operation::Minus => "-".to_owned(),
+            Operation::Divide => "/".to_owned(),
+            Operation::Modulo => "%".to_owned(),
+        }
+    }
+}
+
+impl ToString for PrefixOp {
+    fn to_string(&self) -> String {
+        match self {
+            PrefixOp::Not => "!".to_owned(),
+            PrefixOp::Neg => "-".to_owned(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Expr;
+    use crate::lexer::Lexer;
+
+    /// Parses `source` as one expression, showing how it was grouped, or the
+    /// error as `line:col: message`.
+    fn parse(source: &str) -> Result<String, String> {
+        let tokens = Lexer::new().tokenize(source).unwrap();
+        Expr::new(&tokens, &mut 0)
+            .map(|expr| expr.to_string())
+            .map_err(|error| format!("{}:{}: {}", error.span.line, error.span.col, error.message))
+    }
+
+    #[test]
+    fn left_associative() {
+        assert_eq!(
+            parse("a - b - c"),
+            Ok(r#"((Name("a") - Name("b")) - Name("c"))"#.to_string())
+        );
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(
+            parse("a + b * c"),
+            Ok(r#"(Name("a") + (Name("b") * Name("c")))"#.to_string())
+        );
+        assert_eq!(
+            parse("a || b && c == d"),
+            Ok(r#"(Name("a") || (Name("b") && (Name("c") == Name("d"))))"#.to_string())
+        );
+        assert_eq!(
+            parse("!a == b"),
+            Ok(r#"((!Name("a")) == Name("b"))"#.to_string())
+        );
+    }
+
+    #[test]
+    fn grouping() {
+        assert_eq!(
+            parse("(a + b) * c"),
+            Ok(r#"((Name("a") + Name("b")) * Name("c"))"#.to_string())
+        );
+    }
+
+    #[test]
+    fn chained_comparison() {
+        assert_eq!(
+            parse("1 < 2 < 3"),
+            Err("1:7: comparison operators cannot be chained".to_string())
+        );
+    }
+
+    #[test]
+    fn negative_literal() {
+        assert_eq!(parse("-128i8"), Ok("IntLit(-128, Some(I8))".to_string()));
+        // Only a literal right after the `-` is folded.
+        assert_eq!(parse("-(128)"), Ok("(-IntLit(128, None))".to_string()));
+    }
+
+    #[test]
+    fn prefix_binds_looser_than_indexing() {
+        assert_eq!(
+            parse("-s[0]"),
+            Ok(r#"(-Name("s")[IntLit(0, None)])"#.to_string())
+        );
+    }
+}
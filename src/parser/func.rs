@@ -0,0 +1,126 @@
+use crate::diagnostic::Diagnostic;
+use crate::lexer::Span;
+use crate::parser::decls::Decls;
+use crate::parser::stmts::{expect_body_end, Stmts};
+use crate::parser::var::Var;
+use crate::parser::{expect, span_from};
+use crate::{Lexeme, Token};
+
+pub(crate) struct Param {
+    pub(crate) ty: Var,
+    pub(crate) name: String,
+    pub(crate) span: Span,
+}
+
+/// A function definition: `fn name(int a, int b) -> int { ... }`. Leaving
+/// out the `-> type` makes a function that returns nothing.
+pub(crate) struct Func {
+    pub(crate) name: String,
+    pub(crate) params: Vec<Param>,
+    pub(crate) ret: Option<Var>,
+    pub(crate) decls: Option<Box<Decls>>,
+    pub(crate) stmts: Option<Box<Stmts>>,
+    pub(crate) span: Span,
+}
+
+impl Param {
+    fn new(lex: &Lexeme, x: &mut usize) -> Result<Self, Diagnostic> {
+        let start = *x;
+        let ty = Var::parse(lex, x)?;
+        let name = match &lex[*x].token {
+            Token::Name(name) => name,
+            _ => return Err(Diagnostic::expected("a parameter name", &lex[*x])),
+        };
+        *x += 1;
+        Ok(Self {
+            ty,
+            name: name.to_owned(),
+            span: span_from(lex, start, *x),
+        })
+    }
+}
+
+impl Func {
+    pub fn new(lex: &Lexeme, x: &mut usize) -> Result<Self, Diagnostic> {
+        let start = *x;
+        expect(lex, x, Token::Fn)?;
+        let name = match &lex[*x].token {
+            Token::Name(name) => name,
+            _ => return Err(Diagnostic::expected("a function name", &lex[*x])),
+        };
+        *x += 1;
+
+        expect(lex, x, Token::OpenParen)?;
+        let mut params = vec![];
+        if let Token::CloseParen = lex[*x].token {
+            *x += 1;
+        } else {
+            loop {
+                params.push(Param::new(lex, x)?);
+                match lex[*x].token {
+                    Token::Comma => *x += 1,
+                    _ => {
+                        expect(lex, x, Token::CloseParen)?;
+                        break;
+                    }
+                }
+            }
+        }
+
+        let ret = match lex[*x].token {
+            Token::Arrow => {
+                *x += 1;
+                Some(Var::parse(lex, x)?)
+            }
+            _ => None,
+        };
+
+        expect(lex, x, Token::OpenBrace)?;
+        let decls = Decls::new(lex, x)?;
+        let stmts = Stmts::new(lex, x)?;
+        expect_body_end(lex, x, Token::CloseBrace)?;
+        Ok(Self {
+            name: name.to_owned(),
+            params,
+            ret,
+            decls,
+            stmts,
+            span: span_from(lex, start, *x),
+        })
+    }
+
+    /// Parses any function definitions starting at `x`.
+    pub fn parse_all(lex: &Lexeme, x: &mut usize, funcs: &mut Vec<Func>) -> Result<(), Diagnostic> {
+        while let Token::Fn = lex[*x].token {
+            funcs.push(Func::new(lex, x)?);
+        }
+        Ok(())
+    }
+}
+
+impl ToString for Func {
+    fn to_string(&self) -> String {
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .map(|param| param.ty.to_string() + " " + &param.name)
+            .collect();
+        format!(
+            "fn {}({}){}{{\n{}{}\n}}\n",
+            self.name,
+            params.join(", "),
+            match &self.ret {
+                Some(ret) => " -> ".to_string() + &ret.to_string(),
+                None => "".to_string(),
+            },
+            match &self.decls {
+                None => "".to_string(),
+                Some(decls) => decls.to_string(),
+            },
+            match &self.stmts {
+                None => "".to_string(),
+                Some(stmts) => stmts.to_string(),
+            }
+        )
+    }
+}
@@ -1,5 +1,27 @@
+use std::mem;
+
+use crate::diagnostic::Diagnostic;
+use crate::lexer::Span;
+use crate::{Lexeme, Token};
+
 pub mod decls;
 pub mod expr;
+pub mod func;
 pub mod program;
 pub mod stmts;
 pub mod var;
+
+/// Consumes the token at `x` if it is the same kind of token as `expected`.
+pub(crate) fn expect(lex: &Lexeme, x: &mut usize, expected: Token) -> Result<(), Diagnostic> {
+    if mem::discriminant(&lex[*x].token) == mem::discriminant(&expected) {
+        *x += 1;
+        Ok(())
+    } else {
+        Err(Diagnostic::expected(&expected.to_string(), &lex[*x]))
+    }
+}
+
+/// Span covering the tokens from `start` up to, but not including, `x`.
+pub(crate) fn span_from(lex: &Lexeme, start: usize, x: usize) -> Span {
+    lex[start].span.to(lex[x - 1].span)
+}
@@ -1,17 +1,109 @@
+use crate::diagnostic::Diagnostic;
+use crate::lexer::SpannedToken;
 use crate::parser::decls::Decls;
-use crate::parser::stmts::Stmts;
-use crate::Lexeme;
+use crate::parser::func::Func;
+use crate::parser::stmts::{expect_body_end, Stmts};
+use crate::{Lexeme, Token};
 
 pub struct Program {
+    pub(crate) funcs: Vec<Func>,
     pub(crate) decls: Option<Box<Decls>>,
     pub(crate) stmts: Option<Box<Stmts>>,
 }
 
 impl Program {
-    pub fn new(lex: &Lexeme, x: &mut usize) -> Self {
-        Self {
-            decls: Decls::new(lex, x),
-            stmts: Stmts::new(lex, x),
+    /// Parses the whole token stream. Function definitions may come before,
+    /// between or after the top-level declarations and statements, which make
+    /// up the body of `main`. After a syntax error the parser skips
+    /// ahead to the next declaration or statement and keeps going, so every
+    /// error in the file is reported at once.
+    pub fn new(lex: &Lexeme, x: &mut usize) -> Result<Self, Vec<Diagnostic>> {
+        let mut errors = vec![];
+        let mut open_braces = 0;
+        loop {
+            let start = *x;
+            match Self::parse(lex, x) {
+                Ok(program) if errors.is_empty() => return Ok(program),
+                Ok(_) => return Err(errors),
+                Err(err) => {
+                    // A `}` closing a block abandoned by an earlier error is
+                    // not worth a second error.
+                    let depth = brace_depth(&lex[start..*x]);
+                    match lex[*x].token {
+                        Token::CloseBrace if depth == 0 && open_braces > 0 => {}
+                        _ => errors.push(err),
+                    }
+                    // Blocks the failed parse opened are still open.
+                    open_braces += depth;
+                    open_braces = (open_braces + synchronize(lex, x)).max(0);
+                }
+            }
+        }
+    }
+
+    fn parse(lex: &Lexeme, x: &mut usize) -> Result<Self, Diagnostic> {
+        let mut funcs = vec![];
+        Func::parse_all(lex, x, &mut funcs)?;
+        let decls = Decls::new(lex, x)?;
+        Func::parse_all(lex, x, &mut funcs)?;
+        let stmts = Stmts::new(lex, x)?;
+        Func::parse_all(lex, x, &mut funcs)?;
+        match lex[*x].token {
+            Token::EOF | Token::Int(_) | Token::Str | Token::Bool => {
+                expect_body_end(lex, x, Token::EOF)?
+            }
+            Token::CloseBrace => return Err(Diagnostic::new("unmatched `}`", lex[*x].span)),
+            _ => return Err(Diagnostic::expected("a declaration or statement", &lex[*x])),
+        }
+        Ok(Self {
+            funcs,
+            decls,
+            stmts,
+        })
+    }
+}
+
+/// How many more braces `tokens` open than they close.
+fn brace_depth(tokens: &[SpannedToken]) -> i32 {
+    tokens
+        .iter()
+        .map(|token| match token.token {
+            Token::OpenBrace => 1,
+            Token::CloseBrace => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Moves past the token an error was reported at, up to the start of the
+/// next declaration or statement, or a `}` that may close a block the error
+/// was in. Returns how many more braces were opened than closed in the
+/// skipped tokens.
+fn synchronize(lex: &Lexeme, x: &mut usize) -> i32 {
+    let mut braces = 0;
+    if let Token::EOF = lex[*x].token {
+        return braces;
+    }
+    loop {
+        match lex[*x].token {
+            Token::OpenBrace => braces += 1,
+            Token::CloseBrace => braces -= 1,
+            _ => {}
+        }
+        *x += 1;
+        match lex[*x].token {
+            Token::Int(_)
+            | Token::Str
+            | Token::Bool
+            | Token::If
+            | Token::While
+            | Token::Do
+            | Token::Fn
+            | Token::Return
+            | Token::Print
+            | Token::CloseBrace
+            | Token::EOF => return braces,
+            _ => {}
         }
     }
 }
@@ -19,7 +111,11 @@ impl Program {
 impl ToString for Program {
     fn to_string(&self) -> String {
         format!(
-            "Program {{\n{}{}\n}}",
+            "Program {{\n{}{}{}\n}}",
+            self.funcs
+                .iter()
+                .map(|func| func.to_string())
+                .collect::<String>(),
             &match &self.decls {
                 None => {
                     "".to_string()
@@ -39,3 +135,61 @@ impl ToString for Program {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Program;
+    use crate::lexer::Lexer;
+
+    /// Parses `source`, which must lex, and returns each error as
+    /// `line:col: message`.
+    fn errors(source: &str) -> Vec<String> {
+        let tokens = Lexer::new().tokenize(source).unwrap();
+        match Program::new(&tokens, &mut 0) {
+            Ok(_) => vec![],
+            Err(errors) => errors
+                .iter()
+                .map(|error| format!("{}:{}: {}", error.span.line, error.span.col, error.message))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn reports_every_statement() {
+        assert_eq!(
+            errors("int x = )\nprint(1 +)\nprint(2)\n"),
+            [
+                "1:9: expected an expression, found `)`",
+                "2:10: expected an expression, found `)`",
+            ]
+        );
+    }
+
+    #[test]
+    fn closing_brace_of_abandoned_block() {
+        assert_eq!(
+            errors("int x = 1\nwhile (x < 3) {\n    x = )\n    print(1)\n}\nprint(2)\n"),
+            ["3:9: expected an expression, found `)`"]
+        );
+    }
+
+    #[test]
+    fn error_at_closing_brace() {
+        assert_eq!(
+            errors("fn f() {\n    print(\n}\nprint(1)\n"),
+            ["3:1: expected an expression, found `}`"]
+        );
+    }
+
+    #[test]
+    fn unmatched_closing_brace() {
+        assert_eq!(errors("print(1)\n}\nprint(2)\n"), ["2:1: unmatched `}`"]);
+        assert_eq!(
+            errors("if (true) {\n    while (true) {\n        x = )\n    }\n}\n}\nprint(1)\n"),
+            [
+                "3:13: expected an expression, found `)`",
+                "6:1: unmatched `}`",
+            ]
+        );
+    }
+}
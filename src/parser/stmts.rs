@@ -1,5 +1,8 @@
+use crate::diagnostic::Diagnostic;
+use crate::lexer::Span;
 use crate::parser::decls::Decls;
-use crate::parser::expr::Expr;
+use crate::parser::expr::{parse_args, starts_expr, Expr};
+use crate::parser::{expect, span_from};
 use crate::{Lexeme, Token};
 
 pub struct Stmts {
@@ -9,6 +12,7 @@ pub struct Stmts {
 
 pub struct PrintStmt {
     pub expr: Box<Expr>,
+    pub span: Span,
 }
 
 pub struct ControlStmt {
@@ -16,17 +20,65 @@ pub struct ControlStmt {
     pub control_type: ControlType,
     pub(crate) decls: Option<Box<Decls>>,
     pub stmts: Option<Box<Stmts>>,
+    pub else_branch: Option<ElseBranch>,
+    pub span: Span,
+}
+
+/// What follows the closing brace of an `if`.
+pub enum ElseBranch {
+    ElseIf(Box<ControlStmt>),
+    Else {
+        decls: Option<Box<Decls>>,
+        stmts: Option<Box<Stmts>>,
+    },
 }
 
 pub struct AssignStmt {
     pub name: String,
     pub expr: Box<Expr>,
+    pub span: Span,
+}
+
+pub struct ReturnStmt {
+    pub expr: Option<Box<Expr>>,
+    pub span: Span,
+}
+
+pub struct CallStmt {
+    pub name: String,
+    pub args: Vec<Expr>,
+    pub span: Span,
 }
 
 pub enum StmtType {
     Control(Box<ControlStmt>),
     Print(Box<PrintStmt>),
     Assign(Box<AssignStmt>),
+    Return(Box<ReturnStmt>),
+    Call(Box<CallStmt>),
+}
+
+impl StmtType {
+    pub fn span(&self) -> Span {
+        match self {
+            StmtType::Control(control) => control.span,
+            StmtType::Print(print) => print.span,
+            StmtType::Assign(assign) => assign.span,
+            StmtType::Return(ret) => ret.span,
+            StmtType::Call(call) => call.span,
+        }
+    }
+}
+
+impl StmtType {
+    /// Whether executing this statement always ends in a `return`.
+    pub fn always_returns(&self) -> bool {
+        match self {
+            StmtType::Return(_) => true,
+            StmtType::Control(control) => control.always_returns(),
+            _ => false,
+        }
+    }
 }
 
 impl ToString for StmtType {
@@ -35,6 +87,8 @@ impl ToString for StmtType {
             StmtType::Control(control) => control.to_string(),
             StmtType::Print(print) => print.to_string(),
             StmtType::Assign(assign) => assign.to_string(),
+            StmtType::Return(ret) => ret.to_string(),
+            StmtType::Call(call) => call.to_string(),
         }
     }
 }
@@ -42,48 +96,70 @@ impl ToString for StmtType {
 pub enum ControlType {
     If,
     While,
+    DoWhile,
 }
 
 impl ControlStmt {
-    fn parse(lex: &Lexeme, x: &mut usize) -> Option<Box<ControlStmt>>
-    where
-        Self: Sized,
-    {
-        let mut control_type = ControlType::While;
-        match &lex[*x] {
-            Token::If => control_type = ControlType::If,
-            Token::While => control_type = ControlType::While,
-            _ => return Option::None,
-        }
-        *x += 1;
-        match &lex[*x] {
-            Token::OpenParen => {}
-            _ => return Option::None,
-        }
-        *x += 1;
-        let e = Expr::new(lex, x);
-        match &lex[*x] {
-            Token::CloseParen => {}
-            _ => return Option::None,
-        }
-        *x += 1;
-        match &lex[*x] {
-            Token::OpenBrace => {}
-            _ => return Option::None,
-        }
-        *x += 1;
-        let decls = Decls::new(lex, x);
-        let stmts = Stmts::new(lex, x);
-        match &lex[*x] {
-            Token::CloseBrace => {}
-            _ => return Option::None,
+    fn always_returns(&self) -> bool {
+        let body_returns = self
+            .stmts
+            .as_ref()
+            .is_some_and(|stmts| stmts.always_returns());
+        match self.control_type {
+            ControlType::If => {
+                body_returns
+                    && match &self.else_branch {
+                        None => false,
+                        Some(ElseBranch::ElseIf(control)) => control.always_returns(),
+                        Some(ElseBranch::Else { stmts, .. }) => {
+                            stmts.as_ref().is_some_and(|stmts| stmts.always_returns())
+                        }
+                    }
+            }
+            // The body of a while loop might never run.
+            ControlType::While => false,
+            ControlType::DoWhile => body_returns,
         }
+    }
+
+    fn parse(lex: &Lexeme, x: &mut usize) -> Result<Box<ControlStmt>, Diagnostic> {
+        let start = *x;
+        let control_type = match &lex[*x].token {
+            Token::If => ControlType::If,
+            Token::While => ControlType::While,
+            Token::Do => ControlType::DoWhile,
+            _ => return Err(Diagnostic::expected("`if`, `while` or `do`", &lex[*x])),
+        };
         *x += 1;
-        Some(Box::new(ControlStmt {
-            bool: e.unwrap(),
+        let cond = match control_type {
+            ControlType::DoWhile => None,
+            _ => Some(parse_condition(lex, x)?),
+        };
+        expect(lex, x, Token::OpenBrace)?;
+        let decls = Decls::new(lex, x)?;
+        let stmts = Stmts::new(lex, x)?;
+        expect_body_end(lex, x, Token::CloseBrace)?;
+        let e = match cond {
+            Some(e) => e,
+            None => {
+                expect(lex, x, Token::While)?;
+                parse_condition(lex, x)?
+            }
+        };
+        let else_branch = match (&control_type, &lex[*x].token) {
+            (ControlType::If, Token::Else) => {
+                *x += 1;
+                Some(ElseBranch::parse(lex, x)?)
+            }
+            _ => None,
+        };
+        Ok(Box::new(ControlStmt {
+            bool: e,
             control_type,
             decls,
             stmts,
+            else_branch,
+            span: span_from(lex, start, *x),
         }))
     }
 
@@ -96,42 +172,76 @@ impl ControlStmt {
             None => "".to_string(),
             Some(stmts) => stmts.to_string(),
         };
-        format!(
-            "{}({}){{\n{}{}\n}}",
-            match self.control_type {
-                ControlType::If => {
-                    "if"
-                }
-                ControlType::While => {
-                    "while"
-                }
-            },
-            self.bool.to_string(),
-            decl_string,
-            stmt_string
-        )
+        let else_string = match &self.else_branch {
+            None => "".to_string(),
+            Some(else_branch) => else_branch.to_string(),
+        };
+        match self.control_type {
+            ControlType::DoWhile => format!(
+                "do{{\n{}{}\n}}while({})",
+                decl_string,
+                stmt_string,
+                self.bool.to_string()
+            ),
+            _ => format!(
+                "{}({}){{\n{}{}\n}}{}",
+                match self.control_type {
+                    ControlType::If => {
+                        "if"
+                    }
+                    _ => {
+                        "while"
+                    }
+                },
+                self.bool.to_string(),
+                decl_string,
+                stmt_string,
+                else_string
+            ),
+        }
+    }
+}
+
+impl ElseBranch {
+    /// Parses what follows an `else` keyword.
+    fn parse(lex: &Lexeme, x: &mut usize) -> Result<ElseBranch, Diagnostic> {
+        if let Token::If = lex[*x].token {
+            return Ok(ElseBranch::ElseIf(ControlStmt::parse(lex, x)?));
+        }
+        expect(lex, x, Token::OpenBrace)?;
+        let decls = Decls::new(lex, x)?;
+        let stmts = Stmts::new(lex, x)?;
+        expect_body_end(lex, x, Token::CloseBrace)?;
+        Ok(ElseBranch::Else { decls, stmts })
+    }
+
+    fn to_string(&self) -> String {
+        match self {
+            ElseBranch::ElseIf(control) => format!("else {}", control.to_string()),
+            ElseBranch::Else { decls, stmts } => format!(
+                "else{{\n{}{}\n}}",
+                decls
+                    .as_ref()
+                    .map_or("".to_string(), |decls| decls.to_string()),
+                stmts
+                    .as_ref()
+                    .map_or("".to_string(), |stmts| stmts.to_string())
+            ),
+        }
     }
 }
 
 impl PrintStmt {
-    fn parse(lex: &Lexeme, x: &mut usize) -> Option<Box<PrintStmt>> {
-        match &lex[*x] {
-            Token::Print => {}
-            _ => return Option::None,
-        };
-        *x += 1;
-        match &lex[*x] {
-            Token::OpenParen => {}
-            _ => return Option::None,
-        };
-        *x += 1;
-        let e = Expr::new(lex, x);
-        match &lex[*x] {
-            Token::CloseParen => {}
-            _ => return Option::None,
-        };
-        *x += 1;
-        Some(Box::new(PrintStmt { expr: e.unwrap() }))
+    fn parse(lex: &Lexeme, x: &mut usize) -> Result<Box<PrintStmt>, Diagnostic> {
+        let start = *x;
+        expect(lex, x, Token::Print)?;
+        expect(lex, x, Token::OpenParen)?;
+        let e = Expr::new(lex, x)?;
+        expect(lex, x, Token::CloseParen)?;
+        Ok(Box::new(PrintStmt {
+            expr: e,
+            span: span_from(lex, start, *x),
+        }))
     }
 
     fn to_string(&self) -> String {
@@ -140,67 +250,123 @@ impl PrintStmt {
 }
 
 impl AssignStmt {
-    fn parse(lex: &Lexeme, x: &mut usize) -> Option<Box<AssignStmt>> {
-        let mut name = "";
-        match &lex[*x] {
-            Token::Name(real_name) => name = real_name,
-            _ => return Option::None,
-        };
-        *x += 1;
-        match &lex[*x] {
-            Token::Assign => {}
-            _ => return Option::None,
+    fn parse(lex: &Lexeme, x: &mut usize) -> Result<Box<AssignStmt>, Diagnostic> {
+        let start = *x;
+        let name = match &lex[*x].token {
+            Token::Name(name) => name,
+            _ => return Err(Diagnostic::expected("a variable name", &lex[*x])),
         };
         *x += 1;
-        if let Some(e) = Expr::new(lex, x) {
-            return Some(Box::new(AssignStmt {
-                expr: e,
-                name: name.to_string(),
-            }));
-        }
-        None
+        expect(lex, x, Token::Assign)?;
+        let e = Expr::new(lex, x)?;
+        Ok(Box::new(AssignStmt {
+            expr: e,
+            name: name.to_string(),
+            span: span_from(lex, start, *x),
+        }))
     }
     fn to_string(&self) -> String {
         format!("assign {} = {}", self.name, self.expr.to_string())
     }
 }
 
-fn parse_stmt(lex: &Lexeme, x: &mut usize) -> Option<StmtType> {
-    let save = x.clone();
-    match PrintStmt::parse(lex, x) {
-        None => {
-            *x = save.clone();
-            match ControlStmt::parse(lex, x) {
-                None => {
-                    *x = save.clone();
-                    match AssignStmt::parse(lex, x) {
-                        Some(assign) => Some(StmtType::Assign(assign)),
-                        None => {
-                            *x = save.clone();
-                            None
-                        }
-                    }
-                }
-                Some(if_stmt) => Some(StmtType::Control(if_stmt)),
-            }
+impl ReturnStmt {
+    /// A `return` takes a value unless the block ends right after it, or the
+    /// next token cannot start an expression.
+    fn parse(lex: &Lexeme, x: &mut usize) -> Result<Box<ReturnStmt>, Diagnostic> {
+        let start = *x;
+        expect(lex, x, Token::Return)?;
+        let expr = if starts_expr(&lex[*x].token) {
+            Some(Expr::new(lex, x)?)
+        } else {
+            None
+        };
+        Ok(Box::new(ReturnStmt {
+            expr,
+            span: span_from(lex, start, *x),
+        }))
+    }
+
+    fn to_string(&self) -> String {
+        match &self.expr {
+            Some(expr) => format!("return {}", expr.to_string()),
+            None => "return".to_string(),
         }
-        Some(print) => Some(StmtType::Print(print)),
+    }
+}
+
+impl CallStmt {
+    fn parse(lex: &Lexeme, x: &mut usize) -> Result<Box<CallStmt>, Diagnostic> {
+        let start = *x;
+        let name = match &lex[*x].token {
+            Token::Name(name) => name,
+            _ => return Err(Diagnostic::expected("a function name", &lex[*x])),
+        };
+        *x += 1;
+        let args = parse_args(lex, x)?;
+        Ok(Box::new(CallStmt {
+            name: name.to_string(),
+            args,
+            span: span_from(lex, start, *x),
+        }))
+    }
+
+    fn to_string(&self) -> String {
+        let args: Vec<String> = self.args.iter().map(|arg| arg.to_string()).collect();
+        format!("{}({})", self.name, args.join(", "))
+    }
+}
+
+/// Parses the parenthesised condition of a control statement.
+fn parse_condition(lex: &Lexeme, x: &mut usize) -> Result<Box<Expr>, Diagnostic> {
+    expect(lex, x, Token::OpenParen)?;
+    let e = Expr::new(lex, x)?;
+    expect(lex, x, Token::CloseParen)?;
+    Ok(e)
+}
+
+fn parse_stmt(lex: &Lexeme, x: &mut usize) -> Result<Option<StmtType>, Diagnostic> {
+    let stmt = match &lex[*x].token {
+        Token::Print => StmtType::Print(PrintStmt::parse(lex, x)?),
+        Token::If | Token::While | Token::Do => StmtType::Control(ControlStmt::parse(lex, x)?),
+        Token::Name(_) => match lex[*x + 1].token {
+            Token::OpenParen => StmtType::Call(CallStmt::parse(lex, x)?),
+            _ => StmtType::Assign(AssignStmt::parse(lex, x)?),
+        },
+        Token::Return => StmtType::Return(ReturnStmt::parse(lex, x)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(stmt))
+}
+
+/// Checks that a block of declarations followed by statements is closed by
+/// `end`, calling out declarations that were placed after the statements.
+pub(crate) fn expect_body_end(lex: &Lexeme, x: &mut usize, end: Token) -> Result<(), Diagnostic> {
+    match lex[*x].token {
+        Token::Int(_) | Token::Str | Token::Bool => Err(Diagnostic::new(
+            "declarations must come before statements",
+            lex[*x].span,
+        )),
+        _ => expect(lex, x, end),
     }
 }
 
 impl Stmts {
-    pub fn new(lex: &Lexeme, x: &mut usize) -> Option<Box<Self>> {
-        let save = x.clone();
-        let st = parse_stmt(lex, x);
-        match st {
-            None => {
-                *x = save;
-                None
-            }
-            Some(stmt) => Some(Box::new(Self {
+    pub fn always_returns(&self) -> bool {
+        self.stmt.always_returns()
+            || self
+                .stmts
+                .as_ref()
+                .is_some_and(|stmts| stmts.always_returns())
+    }
+
+    pub fn new(lex: &Lexeme, x: &mut usize) -> Result<Option<Box<Self>>, Diagnostic> {
+        match parse_stmt(lex, x)? {
+            None => Ok(None),
+            Some(stmt) => Ok(Some(Box::new(Self {
                 stmt,
-                stmts: Stmts::new(lex, x),
-            })),
+                stmts: Stmts::new(lex, x)?,
+            }))),
         }
     }
 }
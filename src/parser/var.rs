@@ -1,15 +1,33 @@
+use crate::diagnostic::Diagnostic;
+use crate::lexer::IntTy;
+use crate::{Lexeme, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Var {
     Str,
-    Int,
+    Int(IntTy),
     Bool,
 }
 
+impl Var {
+    pub(crate) fn parse(lex: &Lexeme, x: &mut usize) -> Result<Self, Diagnostic> {
+        let ty = match lex[*x].token {
+            Token::Int(ty) => Var::Int(ty),
+            Token::Str => Var::Str,
+            Token::Bool => Var::Bool,
+            _ => return Err(Diagnostic::expected("a type", &lex[*x])),
+        };
+        *x += 1;
+        Ok(ty)
+    }
+}
+
 impl ToString for Var {
     fn to_string(&self) -> String {
         match self {
             Var::Bool => "bool",
             Var::Str => "str",
-            Var::Int => "int",
+            Var::Int(ty) => ty.name(),
         }
         .parse()
         .unwrap()
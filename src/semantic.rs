@@ -0,0 +1,552 @@
+use std::collections::HashMap;
+
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{IntTy, Span};
+use crate::parser::{
+    decls::{Decl, Decls},
+    expr::{Expr, ExprData, Operation, PrefixOp},
+    func::Func,
+    program::Program,
+    stmts::{ControlStmt, ElseBranch, ReturnStmt, StmtType, Stmts},
+    var::Var,
+};
+
+/// The type of every expression in a checked program, keyed by its span.
+/// Code generation reads it for what the AST leaves implicit, like the width
+/// of a literal or whether a division is signed.
+pub(crate) type Types = HashMap<Span, Var>;
+
+struct Signature {
+    params: Vec<Var>,
+    ret: Option<Var>,
+}
+
+/// Resolves names and checks types across the whole program, so code
+/// generation only ever sees programs that make sense.
+pub(crate) struct Checker {
+    /// The variables of each enclosing block, innermost last, with where
+    /// they were declared.
+    variables: Vec<HashMap<String, (Var, Span)>>,
+    /// Variables whose block has already ended, kept for a better error
+    /// when they are used afterwards.
+    out_of_scope: HashMap<String, Span>,
+    functions: HashMap<String, Signature>,
+    /// Return type of the function being checked, `None` for `main` and
+    /// functions that return nothing.
+    ret: Option<Var>,
+    types: Types,
+    errors: Vec<Diagnostic>,
+}
+
+impl Checker {
+    /// Checks `program`, reporting every error found rather than just the
+    /// first.
+    pub fn check(program: &Program) -> Result<Types, Vec<Diagnostic>> {
+        let mut checker = Checker {
+            variables: vec![],
+            out_of_scope: HashMap::new(),
+            functions: HashMap::new(),
+            ret: None,
+            types: HashMap::new(),
+            errors: vec![],
+        };
+        checker.check_program(program);
+        if checker.errors.is_empty() {
+            Ok(checker.types)
+        } else {
+            Err(checker.errors)
+        }
+    }
+
+    fn error(&mut self, message: impl Into<String>, span: Span) {
+        self.errors.push(Diagnostic::new(message, span));
+    }
+
+    fn check_program(&mut self, program: &Program) {
+        // Every signature is known before any body is checked, so functions
+        // can call each other regardless of order.
+        for func in &program.funcs {
+            if builtin(&func.name).is_some() {
+                self.error(
+                    format!(
+                        "`{}` is a builtin function and cannot be redefined",
+                        func.name
+                    ),
+                    func.span,
+                );
+            } else if RESERVED.contains(&func.name.as_str()) {
+                self.error(
+                    format!(
+                        "`{}` is reserved and cannot be used as a function name",
+                        func.name
+                    ),
+                    func.span,
+                );
+            } else if self.functions.contains_key(&func.name) {
+                self.error(
+                    format!("function `{}` is defined more than once", func.name),
+                    func.span,
+                );
+            } else {
+                let signature = Signature {
+                    params: func.params.iter().map(|param| param.ty).collect(),
+                    ret: func.ret,
+                };
+                self.functions.insert(func.name.to_string(), signature);
+            }
+        }
+        for func in &program.funcs {
+            self.check_function(func);
+        }
+
+        self.variables = vec![HashMap::new()];
+        self.out_of_scope.clear();
+        self.ret = None;
+        if let Some(decls) = &program.decls {
+            self.check_decls(decls);
+        }
+        if let Some(stmts) = &program.stmts {
+            self.check_stmts(stmts);
+        }
+    }
+
+    fn check_function(&mut self, func: &Func) {
+        // Parameters live in the same scope as the top level of the body.
+        self.variables = vec![HashMap::new()];
+        self.out_of_scope.clear();
+        self.ret = func.ret;
+        for param in &func.params {
+            self.declare(&param.name, param.ty, param.span);
+        }
+        if let Some(decls) = &func.decls {
+            self.check_decls(decls);
+        }
+        if let Some(stmts) = &func.stmts {
+            self.check_stmts(stmts);
+        }
+
+        let always_returns = func
+            .stmts
+            .as_ref()
+            .is_some_and(|stmts| stmts.always_returns());
+        if func.ret.is_some() && !always_returns {
+            self.error(
+                format!(
+                    "function `{}` can finish without returning a value",
+                    func.name
+                ),
+                func.span,
+            );
+        }
+    }
+
+    fn check_decls(&mut self, decls: &Decls) {
+        self.check_decl(&decls.decl);
+        if let Some(decls) = &decls.decls {
+            self.check_decls(decls);
+        }
+    }
+
+    fn check_decl(&mut self, decl: &Decl) {
+        let found = self.expr_type(&decl.expr, Some(decl.ty));
+        self.check_type(decl.ty, found, decl.expr.span());
+        self.declare(&decl.name, decl.ty, decl.span);
+    }
+
+    /// Adds a variable to the innermost scope. It may shadow a variable of an
+    /// enclosing block, but not one declared in the same block.
+    fn declare(&mut self, name: &str, ty: Var, span: Span) {
+        let scope = self.variables.last_mut().unwrap();
+        if scope.contains_key(name) {
+            self.error(
+                format!("`{}` is already declared in this scope", name),
+                span,
+            );
+        } else {
+            scope.insert(name.to_string(), (ty, span));
+        }
+    }
+
+    /// Checks the body of an `if`, `else` or loop. Variables declared in it
+    /// go out of scope at its closing brace.
+    fn check_block(&mut self, decls: &Option<Box<Decls>>, stmts: &Option<Box<Stmts>>) {
+        self.variables.push(HashMap::new());
+        if let Some(decls) = decls {
+            self.check_decls(decls);
+        }
+        if let Some(stmts) = stmts {
+            self.check_stmts(stmts);
+        }
+        let scope = self.variables.pop().unwrap();
+        for (name, (_, span)) in scope {
+            self.out_of_scope.insert(name, span);
+        }
+    }
+
+    fn check_stmts(&mut self, stmts: &Stmts) {
+        match &stmts.stmt {
+            StmtType::Control(control) => self.check_control(control),
+            StmtType::Print(print) => {
+                self.expr_type(&print.expr, None);
+            }
+            StmtType::Assign(assign) => {
+                let ty = self.variable(&assign.name, assign.span);
+                let found = self.expr_type(&assign.expr, ty);
+                if let Some(ty) = ty {
+                    self.check_type(ty, found, assign.expr.span());
+                }
+            }
+            StmtType::Return(ret) => self.check_return(ret),
+            StmtType::Call(call) => {
+                self.check_call(&call.name, &call.args, call.span);
+            }
+        }
+        if let Some(stmts) = &stmts.stmts {
+            self.check_stmts(stmts);
+        }
+    }
+
+    fn check_control(&mut self, stmt: &ControlStmt) {
+        let found = self.expr_type(&stmt.bool, Some(Var::Bool));
+        self.check_type(Var::Bool, found, stmt.bool.span());
+        self.check_block(&stmt.decls, &stmt.stmts);
+        match &stmt.else_branch {
+            Some(ElseBranch::ElseIf(control)) => self.check_control(control),
+            Some(ElseBranch::Else { decls, stmts }) => self.check_block(decls, stmts),
+            None => {}
+        }
+    }
+
+    fn check_return(&mut self, stmt: &ReturnStmt) {
+        match (&stmt.expr, self.ret) {
+            (Some(expr), Some(ret)) => {
+                let found = self.expr_type(expr, Some(ret));
+                self.check_type(ret, found, expr.span());
+            }
+            (None, None) => {}
+            (Some(expr), None) => {
+                self.expr_type(expr, None);
+                self.error(
+                    "cannot return a value from a function without a return type",
+                    stmt.span,
+                );
+            }
+            (None, Some(ret)) => self.error(
+                format!("expected a return value of type `{}`", ret.to_string()),
+                stmt.span,
+            ),
+        }
+    }
+
+    /// Reports a mismatch unless `found` is `expected`. A `found` of `None`
+    /// has already been reported.
+    fn check_type(&mut self, expected: Var, found: Option<Var>, span: Span) {
+        match found {
+            Some(found) if found != expected => self.error(
+                format!(
+                    "mismatched types: expected `{}`, found `{}`",
+                    expected.to_string(),
+                    found.to_string()
+                ),
+                span,
+            ),
+            _ => {}
+        }
+    }
+
+    fn variable(&mut self, name: &str, span: Span) -> Option<Var> {
+        let found = self
+            .variables
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name));
+        if let Some((ty, _)) = found {
+            return Some(*ty);
+        }
+        if self.out_of_scope.contains_key(name) {
+            self.error(
+                format!(
+                    "cannot find variable `{}` in this scope, the block it was declared in has ended",
+                    name
+                ),
+                span,
+            );
+        } else {
+            self.error(format!("cannot find variable `{}`", name), span);
+        }
+        None
+    }
+
+    /// The type `expr` evaluates to, or `None` if it contains an error.
+    /// `hint` is the type the surrounding code expects, which integer
+    /// literals without a suffix take on if it is an integer type.
+    fn expr_type(&mut self, expr: &Expr, hint: Option<Var>) -> Option<Var> {
+        let ty = self.infer(expr, hint)?;
+        self.types.insert(expr.span(), ty);
+        Some(ty)
+    }
+
+    fn infer(&mut self, expr: &Expr, hint: Option<Var>) -> Option<Var> {
+        match expr {
+            Expr::Unary(data, span) => match data {
+                ExprData::StrLit(_) => Some(Var::Str),
+                ExprData::IntLit(value, suffix) => {
+                    let ty = match (suffix, hint) {
+                        (Some(ty), _) => *ty,
+                        (None, Some(Var::Int(ty))) => ty,
+                        (None, _) => IntTy::I64,
+                    };
+                    if *value < ty.min() || *value > ty.max() {
+                        self.error(
+                            format!(
+                                "integer literal `{}` is out of range for `{}`",
+                                value,
+                                ty.name()
+                            ),
+                            *span,
+                        );
+                    }
+                    Some(Var::Int(ty))
+                }
+                ExprData::BoolLit(_) => Some(Var::Bool),
+                ExprData::Name(name) => self.variable(name, *span),
+            },
+            Expr::Binary(left, right, op, span) => {
+                // Comparisons give a `bool` whatever their operands are.
+                let hint = match op {
+                    Operation::Equals | Operation::GT | Operation::LT => None,
+                    _ => hint,
+                };
+                // Each operand is a hint for the other, so `x + 1` and `1 + x`
+                // both give the literal the type of `x`.
+                let (left, right) = if is_unsuffixed_int(left) && !is_unsuffixed_int(right) {
+                    let right = self.expr_type(right, hint);
+                    (self.expr_type(left, right.or(hint)), right)
+                } else {
+                    let left = self.expr_type(left, hint);
+                    (left, self.expr_type(right, left.or(hint)))
+                };
+                self.binary_type(left?, right?, op, *span)
+            }
+            Expr::Prefix(op, operand, span) => match (op, self.expr_type(operand, hint)?) {
+                (PrefixOp::Not, Var::Bool) => Some(Var::Bool),
+                (PrefixOp::Neg, Var::Int(ty)) if ty.is_signed() => Some(Var::Int(ty)),
+                (op, ty) => {
+                    self.error(
+                        format!("cannot apply `{}` to `{}`", op.to_string(), ty.to_string()),
+                        *span,
+                    );
+                    None
+                }
+            },
+            Expr::Index(base, index, span) => {
+                let base = self.expr_type(base, None);
+                let index_ty = self.expr_type(index, Some(INT));
+                self.check_type(INT, index_ty, index.span());
+                match base? {
+                    // Indexing a string gives the byte at that position.
+                    Var::Str => Some(INT),
+                    ty => {
+                        self.error(
+                            format!("cannot index into a value of type `{}`", ty.to_string()),
+                            *span,
+                        );
+                        None
+                    }
+                }
+            }
+            Expr::Call(name, args, span) => match self.check_call(name, args, *span)? {
+                Some(ty) => Some(ty),
+                None => {
+                    self.error(
+                        format!("function `{}` does not return a value", name),
+                        *span,
+                    );
+                    None
+                }
+            },
+        }
+    }
+
+    fn binary_type(&mut self, left: Var, right: Var, op: &Operation, span: Span) -> Option<Var> {
+        match (op, left, right) {
+            (
+                Operation::Times
+                | Operation::Plus
+                | Operation::Minus
+                | Operation::Divide
+                | Operation::Modulo,
+                Var::Int(l),
+                Var::Int(r),
+            ) if l == r => Some(left),
+            (Operation::Plus, Var::Str, Var::Str) => Some(Var::Str),
+            (Operation::GT | Operation::LT, Var::Int(l), Var::Int(r)) if l == r => Some(Var::Bool),
+            (Operation::Equals, left, right) if left == right => Some(Var::Bool),
+            (Operation::And | Operation::Or, Var::Bool, Var::Bool) => Some(Var::Bool),
+            _ => {
+                self.error(
+                    format!(
+                        "cannot apply `{}` to `{}` and `{}`",
+                        op.to_string(),
+                        left.to_string(),
+                        right.to_string()
+                    ),
+                    span,
+                );
+                None
+            }
+        }
+    }
+
+    /// Checks a call against the signature of the function it calls. Returns
+    /// `None` if the call is invalid, otherwise the function's return type.
+    fn check_call(&mut self, name: &str, args: &[Expr], span: Span) -> Option<Option<Var>> {
+        let (params, ret) = match (self.functions.get(name), builtin(name)) {
+            (Some(signature), _) => (signature.params.clone(), signature.ret),
+            (None, Some(signature)) => (signature.params, signature.ret),
+            (None, None) => {
+                for arg in args {
+                    self.expr_type(arg, None);
+                }
+                self.error(format!("cannot find function `{}`", name), span);
+                return None;
+            }
+        };
+        let found: Vec<Option<Var>> = args
+            .iter()
+            .enumerate()
+            .map(|(i, arg)| self.expr_type(arg, params.get(i).copied()))
+            .collect();
+        if args.len() != params.len() {
+            self.error(
+                format!(
+                    "function `{}` takes {} argument(s) but {} were given",
+                    name,
+                    params.len(),
+                    args.len()
+                ),
+                span,
+            );
+            return None;
+        }
+        for ((arg, found), param) in args.iter().zip(found).zip(params) {
+            self.check_type(param, found, arg.span());
+        }
+        Some(ret)
+    }
+}
+
+/// `int`, the integer type used where nothing asks for another one.
+const INT: Var = Var::Int(IntTy::I64);
+
+/// Whether `expr` is made only of integer literals without a suffix, so it has
+/// no type of its own until it is used with something that does.
+fn is_unsuffixed_int(expr: &Expr) -> bool {
+    match expr {
+        Expr::Unary(ExprData::IntLit(_, None), _) => true,
+        Expr::Prefix(PrefixOp::Neg, operand, _) => is_unsuffixed_int(operand),
+        Expr::Binary(left, right, op, _) => {
+            !matches!(
+                op,
+                Operation::Equals | Operation::GT | Operation::LT | Operation::And | Operation::Or
+            ) && is_unsuffixed_int(left)
+                && is_unsuffixed_int(right)
+        }
+        _ => false,
+    }
+}
+
+/// Names user functions cannot have. `main` is the program's own entry point.
+/// The rest are functions LLVM may turn copies and comparisons into calls to;
+/// those calls would bind to a user function of the same name even though it
+/// is internal.
+const RESERVED: &[&str] = &["main", "bcmp", "memcmp", "memcpy", "memmove", "memset"];
+
+/// Functions every program can call without defining them.
+fn builtin(name: &str) -> Option<Signature> {
+    match name {
+        "len" => Some(Signature {
+            params: vec![Var::Str],
+            ret: Some(INT),
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Checker;
+    use crate::lexer::Lexer;
+    use crate::parser::program::Program;
+
+    /// Checks `source`, which must lex and parse, and returns each error as
+    /// `line:col: message`.
+    fn errors(source: &str) -> Vec<String> {
+        let tokens = Lexer::new().tokenize(source).unwrap();
+        let program = Program::new(&tokens, &mut 0).unwrap();
+        match Checker::check(&program) {
+            Ok(_) => vec![],
+            Err(errors) => errors
+                .iter()
+                .map(|error| format!("{}:{}: {}", error.span.line, error.span.col, error.message))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn mismatched_declaration() {
+        assert_eq!(
+            errors("int x = \"hello\"\n"),
+            ["1:9: mismatched types: expected `i64`, found `str`"]
+        );
+    }
+
+    #[test]
+    fn undeclared_variable() {
+        assert_eq!(errors("print(y)\n"), ["1:7: cannot find variable `y`"]);
+    }
+
+    #[test]
+    fn non_bool_conditions() {
+        assert_eq!(
+            errors("if (1) {\n    print(1)\n}\n"),
+            ["1:5: mismatched types: expected `bool`, found `i64`"]
+        );
+        assert_eq!(
+            errors("while (1) {\n}\n"),
+            ["1:8: mismatched types: expected `bool`, found `i64`"]
+        );
+    }
+
+    #[test]
+    fn wrong_argument_count() {
+        assert_eq!(
+            errors("fn f(int a) -> int {\n    return a\n}\nprint(f(1, 2))\n"),
+            ["4:7: function `f` takes 1 argument(s) but 2 were given"]
+        );
+    }
+
+    #[test]
+    fn missing_return() {
+        assert_eq!(
+            errors("fn f() -> int {\n    print(1)\n}\n"),
+            ["1:1: function `f` can finish without returning a value"]
+        );
+    }
+
+    #[test]
+    fn use_after_scope() {
+        assert_eq!(
+            errors("if (true) {\n    int x = 1\n}\nprint(x)\n"),
+            ["4:7: cannot find variable `x` in this scope, the block it was declared in has ended"]
+        );
+    }
+
+    #[test]
+    fn reserved_function_name() {
+        assert_eq!(
+            errors("fn memcpy(str s) {\n    print(s)\n}\n"),
+            ["1:1: `memcpy` is reserved and cannot be used as a function name"]
+        );
+    }
+}
@@ -0,0 +1,22 @@
+//! Helpers shared by the integration tests.
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Numbers the paths `temp_path` hands out, since the tests in a file run at
+/// the same time in one process.
+static FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// A path in the temporary directory that no other test uses, for a file
+/// named after `name` with the given extension.
+pub fn temp_path(name: &str, extension: &str) -> PathBuf {
+    env::temp_dir().join(format!(
+        "crabby-{}-{}-{}.{}",
+        name,
+        process::id(),
+        FILES.fetch_add(1, Ordering::Relaxed),
+        extension
+    ))
+}
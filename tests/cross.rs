@@ -0,0 +1,79 @@
+//! Cross compiles `fib.txt` and checks the object files are for the machine
+//! that was asked for.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+mod common;
+
+/// `e_machine` values from the ELF specification.
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+const EM_RISCV: u16 = 243;
+
+fn build_object(target: &str, extra_args: &[&str]) -> (Output, PathBuf) {
+    let fib = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fib.txt");
+    let object = common::temp_path(target, "o");
+    let output = Command::new(env!("CARGO_BIN_EXE_crabby"))
+        .arg("build")
+        .arg(&fib)
+        .arg("--emit=obj")
+        .arg(format!("--target={}", target))
+        .args(extra_args)
+        .arg("-o")
+        .arg(&object)
+        .output()
+        .unwrap();
+    (output, object)
+}
+
+/// Compiles `fib.txt` for `target` and reads the machine type out of the
+/// object file's ELF header.
+fn object_machine(target: &str, extra_args: &[&str]) -> u16 {
+    let (output, object) = build_object(target, extra_args);
+    assert!(
+        output.status.success(),
+        "building for {} failed:\n{}",
+        target,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let bytes = fs::read(&object).unwrap();
+    let _ = fs::remove_file(&object);
+
+    assert_eq!(&bytes[..4], b"\x7fELF", "not an ELF file");
+    // Every target here is little endian.
+    u16::from_le_bytes([bytes[18], bytes[19]])
+}
+
+#[test]
+fn x86_64() {
+    assert_eq!(object_machine("x86_64-unknown-linux-gnu", &[]), EM_X86_64);
+}
+
+#[test]
+fn aarch64() {
+    assert_eq!(object_machine("aarch64-unknown-linux-gnu", &[]), EM_AARCH64);
+}
+
+#[test]
+fn aarch64_with_cpu_and_features() {
+    let args = ["--cpu=cortex-a53", "--features=+neon,+crc"];
+    assert_eq!(
+        object_machine("aarch64-unknown-linux-gnu", &args),
+        EM_AARCH64
+    );
+}
+
+#[test]
+fn riscv64() {
+    let args = ["--features=+m,+a,+c"];
+    assert_eq!(object_machine("riscv64-unknown-linux-gnu", &args), EM_RISCV);
+}
+
+#[test]
+fn unknown_target() {
+    let (output, _) = build_object("nonsense-unknown-none", &[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown target"));
+}
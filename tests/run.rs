@@ -0,0 +1,83 @@
+//! Runs small programs with `crabby run` and checks what they print and how
+//! they exit.
+
+use std::fs;
+use std::process::{Command, Output};
+
+mod common;
+
+/// The exit code of a program stopped by a runtime error.
+const PANIC_CODE: i32 = 101;
+
+fn run(source: &str) -> Output {
+    let path = common::temp_path("run", "txt");
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_crabby"))
+        .arg("run")
+        .arg(&path)
+        .output()
+        .unwrap();
+    let _ = fs::remove_file(&path);
+    output
+}
+
+fn assert_output(output: &Output, stdout: &str, stderr: &str, code: i32) {
+    assert_eq!(String::from_utf8_lossy(&output.stdout), stdout);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), stderr);
+    assert_eq!(output.status.code(), Some(code));
+}
+
+#[test]
+fn while_false_never_runs() {
+    let output = run("while (false) { print(1) }\n");
+    assert_output(&output, "", "", 0);
+}
+
+#[test]
+fn do_while_runs_once() {
+    let output = run("do {\n    print(1)\n} while (false)\n");
+    assert_output(&output, "1\n", "", 0);
+}
+
+#[test]
+fn divide_by_zero() {
+    let output = run("int zero = 0\nprint(1)\nprint(1 / zero)\n");
+    assert_output(
+        &output,
+        "1\n",
+        "error: attempt to divide by zero at line 3, column 7\n",
+        PANIC_CODE,
+    );
+}
+
+#[test]
+fn add_with_overflow() {
+    let output = run("i8 x = 127\nx = x + 1\nprint(x)\n");
+    assert_output(
+        &output,
+        "",
+        "error: attempt to add with overflow at line 2, column 5\n",
+        PANIC_CODE,
+    );
+}
+
+#[test]
+fn string_operations() {
+    let output = run("str a = \"foo\"\n\
+         str b = a + \"bar\"\n\
+         print(b)\n\
+         print(len(b))\n\
+         print(b == \"foobar\")\n\
+         print(b == a)\n\
+         print(b[3])\n");
+    assert_output(&output, "foobar\n6\ntrue\nfalse\n98\n", "", 0);
+}
+
+#[test]
+fn short_circuit() {
+    // Evaluating the right operand would divide by zero.
+    let output = run("int zero = 0\n\
+         print(false && 1 / zero == 1)\n\
+         print(true || 1 / zero == 1)\n");
+    assert_output(&output, "false\ntrue\n", "", 0);
+}